@@ -10,27 +10,62 @@ pub struct BoundingBox {
 }
 
 impl BoundingBox {
+    /// Smallest box containing all `points`, padded along flat axes since
+    /// `is_hit_by` never reports a hit on a box of zero thickness.
+    pub fn from_points(points: &[V3]) -> BoundingBox {
+        const PADDING: f64 = 1e-6;
+        let minimum = points.iter().fold(V3([f64::INFINITY; 3]), |a, &b| a.min(b));
+        let maximum = points
+            .iter()
+            .fold(V3([f64::NEG_INFINITY; 3]), |a, &b| a.max(b));
+        let mut padding = V3::ZERO;
+        for i in 0..3 {
+            if maximum.0[i] - minimum.0[i] < PADDING {
+                padding.0[i] = PADDING;
+            }
+        }
+        BoundingBox {
+            minimum: minimum - padding,
+            maximum: maximum + padding,
+        }
+    }
+
     pub fn is_hit_by(self, ray: Ray, t_min: f64, t_max: f64) -> bool {
+        let direction_inv = ray.direction.map(|x| 1.0 / x);
+        self.intersects(ray.origin, direction_inv, t_min, t_max)
+    }
+
+    /// Slab test against a ray given by its origin and the reciprocal of its
+    /// direction, which callers testing many boxes can compute once.
+    pub fn intersects(self, origin: V3, direction_inv: V3, mut t_min: f64, mut t_max: f64) -> bool {
         for i in 0..3 {
-            let direction_inv = 1.0 / ray.direction.0[i];
-            let mut t0 = direction_inv * (self.minimum.0[i] - ray.origin.0[i]);
-            let mut t1 = direction_inv * (self.maximum.0[i] - ray.origin.0[i]);
-            if direction_inv < 0.0 {
+            let mut t0 = direction_inv.0[i] * (self.minimum.0[i] - origin.0[i]);
+            let mut t1 = direction_inv.0[i] * (self.maximum.0[i] - origin.0[i]);
+            if direction_inv.0[i] < 0.0 {
                 swap(&mut t0, &mut t1)
             }
-            if t0 < t_min {
-                t0 = t_min
+            if t0 > t_min {
+                t_min = t0
             }
-            if t1 > t_max {
-                t1 = t_max
+            if t1 < t_max {
+                t_max = t1
             }
-            if t0 >= t1 {
+            if t_min >= t_max {
                 return false;
             }
         }
         true
     }
 
+    pub fn centroid(self) -> V3 {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn surface_area(self) -> f64 {
+        let V3([x, y, z]) = self.maximum - self.minimum;
+        2.0 * (x * y + y * z + z * x)
+    }
+
     pub fn union(self, rhs: BoundingBox) -> BoundingBox {
         let V3([x_min1, y_min1, z_min1]) = self.minimum;
         let V3([x_max1, y_max1, z_max1]) = self.maximum;
@@ -1,94 +1,455 @@
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rayon::prelude::*;
+
 use crate::bounding_box::BoundingBox;
 use crate::ray::Ray;
 use crate::surface::{RayHitMaterial, Surface};
+use crate::v3::V3;
 
-struct BoundingBoxLeaf {
-    surface: Box<dyn Surface>,
-    bounding_box: BoundingBox,
+#[derive(Clone, Copy)]
+pub enum Builder {
+    /// Splits on the median of the box minimums, cycling through the axes,
+    /// down to a fixed depth.
+    Median,
+    /// Splits where the surface area heuristic estimates the cheapest traversal.
+    Sah(SahOptions),
 }
 
-impl Surface for BoundingBoxLeaf {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial> {
-        if self.bounding_box.is_hit_by(ray, t_min, t_max) {
-            self.surface.hit(ray, t_min, t_max)
-        } else {
-            None
+#[derive(Clone, Copy)]
+pub struct SahOptions {
+    /// Number of buckets along each axis that candidate splits are taken from,
+    /// at least 2.
+    pub bins: usize,
+    /// Nodes with more surfaces than this are always split if possible.
+    pub max_leaf_size: usize,
+    /// Cost of visiting a node, relative to `intersection_cost`.
+    pub traversal_cost: f64,
+    /// Cost of testing a ray against a single surface.
+    pub intersection_cost: f64,
+}
+
+impl Default for SahOptions {
+    fn default() -> SahOptions {
+        SahOptions {
+            bins: 16,
+            max_leaf_size: 4,
+            traversal_cost: 1.0,
+            intersection_cost: 1.0,
         }
     }
+}
 
-    fn calculate_bounding_box(&self) -> BoundingBox {
-        self.bounding_box
+/// Bounding volume hierarchy flattened into a single array of nodes in
+/// depth-first order, so the first child of an inner node directly follows it.
+pub struct BoundingBoxTree {
+    nodes: Vec<Node>,
+    surfaces: Vec<Box<dyn Surface>>,
+}
+
+impl BoundingBoxTree {
+    pub fn surface(&self, index: usize) -> &dyn Surface {
+        &*self.surfaces[index]
+    }
+
+    /// Indices of the surfaces that can be sampled as lights.
+    pub fn lights(&self) -> Vec<usize> {
+        (0..self.surfaces.len())
+            .filter(|&index| self.surfaces[index].is_light())
+            .collect()
     }
 }
 
-struct BoundingBoxTree {
-    left: Box<dyn Surface>,
-    right: Box<dyn Surface>,
+#[derive(Clone, Copy)]
+struct Node {
     bounding_box: BoundingBox,
+    /// Index of the second child for inner nodes, or of the first surface
+    /// for leaves.
+    offset: u32,
+    /// Number of surfaces in a leaf, zero for inner nodes.
+    count: u16,
+    /// Axis the children of an inner node were split along.
+    axis: u8,
 }
 
-impl Surface for BoundingBoxTree {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial> {
-        if self.bounding_box.is_hit_by(ray, t_min, t_max) {
-            let left_hit = self.left.hit(ray, t_min, t_max);
-            let right_hit = self
-                .right
-                .hit(ray, t_min, left_hit.map_or(t_max, |hit| hit.hit.t));
-            right_hit.or(left_hit)
-        } else {
-            None
+/// Depth at which the builders stop splitting.
+const MAX_DEPTH: usize = 64;
+/// Levels that splitting leaves down to sizes fitting a `u16` can add below
+/// `MAX_DEPTH`, as halving fewer than 2^32 surfaces 17 times gets there.
+const MAX_LEAF_SPLIT_DEPTH: usize = 17;
+/// Far children a traversal may have to come back to, one per level.
+const STACK_SIZE: usize = MAX_DEPTH + MAX_LEAF_SPLIT_DEPTH;
+
+impl BoundingBoxTree {
+    /// Nearest hit that `hit` finds on a surface of the tree, given the
+    /// surface and the farthest distance still of interest.
+    fn nearest_hit<'s>(
+        &'s self,
+        ray: Ray,
+        t_min: f64,
+        mut t_max: f64,
+        mut hit: impl FnMut(&'s dyn Surface, f64) -> Option<RayHitMaterial<'s>>,
+    ) -> Option<RayHitMaterial<'s>> {
+        let direction_inv = ray.direction.map(|x| 1.0 / x);
+        let mut nearest_result: Option<RayHitMaterial> = None;
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node
+                .bounding_box
+                .intersects(ray.origin, direction_inv, t_min, t_max)
+            {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for surface in &self.surfaces[start..start + node.count as usize] {
+                        if let Some(result) = hit(&**surface, t_max) {
+                            t_max = result.hit.t;
+                            nearest_result = Some(result);
+                        }
+                    }
+                } else {
+                    // Visit the child nearer to the ray origin first so that
+                    // hits found there can cull the farther one.
+                    let (near, far) = if direction_inv.0[node.axis as usize] < 0.0 {
+                        (node.offset as usize, node_index + 1)
+                    } else {
+                        (node_index + 1, node.offset as usize)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    node_index = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            node_index = stack[stack_len];
         }
+        nearest_result
+    }
+}
+
+impl Surface for BoundingBoxTree {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        self.nearest_hit(ray, t_min, t_max, |surface, t_max| {
+            surface.hit(ray, t_min, t_max)
+        })
+    }
+
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        self.nearest_hit(ray, t_min, t_max, |surface, t_max| {
+            surface.sample_hit(ray, t_min, t_max, rng)
+        })
     }
 
     fn calculate_bounding_box(&self) -> BoundingBox {
-        self.bounding_box
+        self.nodes[0].bounding_box
+    }
+
+    /// Multiplies the transmittance of every surface the ray passes, in no
+    /// particular order, stopping at the first that blocks it.
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, rng: &mut SmallRng) -> f64 {
+        let direction_inv = ray.direction.map(|x| 1.0 / x);
+        let mut transmittance = 1.0;
+
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node
+                .bounding_box
+                .intersects(ray.origin, direction_inv, t_min, t_max)
+            {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    for surface in &self.surfaces[start..start + node.count as usize] {
+                        transmittance *= surface.transmittance(ray, t_min, t_max, rng);
+                        if transmittance == 0.0 {
+                            return 0.0;
+                        }
+                    }
+                } else {
+                    stack[stack_len] = node.offset as usize;
+                    stack_len += 1;
+                    node_index += 1;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            node_index = stack[stack_len];
+        }
+        transmittance
     }
 }
 
-pub fn build(surfaces: Vec<Box<dyn Surface>>) -> Option<Box<dyn Surface>> {
-    let surfaces_with_bounding_box = surfaces
-        .into_iter()
-        .map(|surface| {
+#[derive(Clone, Copy)]
+struct Item {
+    surface_index: usize,
+    bounding_box: BoundingBox,
+    centroid: V3,
+}
+
+/// Builds a tree over `surfaces`, or nothing if there are none.
+///
+/// # Panics
+///
+/// Panics if the builder is SAH with fewer than 2 bins, or if there are
+/// 2^32 surfaces or more.
+pub fn build(surfaces: Vec<Box<dyn Surface>>, builder: Builder) -> Option<BoundingBoxTree> {
+    assert!(surfaces.len() <= u32::MAX as usize, "too many surfaces");
+    if let Builder::Sah(opts) = builder {
+        assert!(
+            opts.bins >= 2,
+            "SAH needs at least 2 bins, got {}",
+            opts.bins
+        );
+    }
+    if surfaces.is_empty() {
+        return None;
+    }
+    let mut items: Vec<Item> = surfaces
+        .iter()
+        .enumerate()
+        .map(|(surface_index, surface)| {
             let bounding_box = surface.calculate_bounding_box();
-            Box::new(BoundingBoxLeaf {
-                surface,
+            Item {
+                surface_index,
                 bounding_box,
-            })
+                centroid: bounding_box.centroid(),
+            }
         })
         .collect();
+
+    let mut nodes = Vec::new();
+    let start = 0;
     let depth = 0;
-    build_rec(surfaces_with_bounding_box, depth)
-}
-
-fn build_rec(mut leaves: Vec<Box<BoundingBoxLeaf>>, depth: usize) -> Option<Box<dyn Surface>> {
-    match leaves.len() {
-        0 => None,
-        1 => leaves.into_iter().next().map(|x| x as Box<dyn Surface>),
-        length => {
-            if depth >= 6 {
-                let bounding_box = leaves.calculate_bounding_box();
-                let surface = Box::new(leaves);
-                Some(Box::new(BoundingBoxLeaf {
-                    surface,
-                    bounding_box,
-                }))
-            } else {
-                let axis = depth % 3;
-                let sort_key = |x: &BoundingBoxLeaf| x.bounding_box.minimum.0[axis];
-                leaves.sort_unstable_by(|a, b| sort_key(a).partial_cmp(&sort_key(b)).unwrap());
-                let right_children = leaves.split_off(length / 2);
-                let left_children = leaves;
-                let left = build_rec(left_children, depth + 1)?;
-                let right = build_rec(right_children, depth + 1)?;
-                let bounding_box = left
-                    .calculate_bounding_box()
-                    .union(right.calculate_bounding_box());
-                Some(Box::new(BoundingBoxTree {
-                    left,
-                    right,
-                    bounding_box,
-                }))
+    build_rec(&mut items, start, depth, &builder, &mut nodes);
+
+    let mut surfaces: Vec<Option<Box<dyn Surface>>> = surfaces.into_iter().map(Some).collect();
+    let surfaces = items
+        .iter()
+        .map(|item| surfaces[item.surface_index].take().unwrap())
+        .collect();
+    Some(BoundingBoxTree { nodes, surfaces })
+}
+
+/// Builds a tree over each object in parallel, for sharing between all of its
+/// instances.
+///
+/// # Panics
+///
+/// Panics if an object has no surfaces, or under the same conditions as
+/// `build`.
+pub fn build_objects(
+    objects: Vec<Vec<Box<dyn Surface>>>,
+    builder: Builder,
+) -> Vec<Arc<BoundingBoxTree>> {
+    objects
+        .into_par_iter()
+        .map(|surfaces| Arc::new(build(surfaces, builder).expect("object without surfaces")))
+        .collect()
+}
+
+/// Appends the subtree over `items` to `nodes`, reordering `items` so that
+/// every leaf refers to a contiguous range starting at `start`.
+fn build_rec(
+    items: &mut [Item],
+    start: usize,
+    depth: usize,
+    builder: &Builder,
+    nodes: &mut Vec<Node>,
+) {
+    let bounding_box = items
+        .iter()
+        .map(|item| item.bounding_box)
+        .reduce(|a, b| a.union(b))
+        .unwrap();
+    let node_index = nodes.len();
+    nodes.push(Node {
+        bounding_box,
+        offset: start as u32,
+        count: items.len() as u16,
+        axis: 0,
+    });
+
+    let split = if depth + 1 >= MAX_DEPTH || items.len() == 1 {
+        None
+    } else {
+        match builder {
+            Builder::Median => median_split(items, depth),
+            Builder::Sah(opts) => sah_split(items, bounding_box, opts),
+        }
+    }
+    .filter(|&(_, mid)| mid > 0 && mid < items.len())
+    .or_else(|| {
+        // Leaf sizes have to fit into a `u16`, whatever the builder decided.
+        if items.len() > u16::MAX as usize {
+            Some(split_at_median(items, depth % 3))
+        } else {
+            None
+        }
+    });
+
+    if let Some((axis, mid)) = split {
+        let (left, right) = items.split_at_mut(mid);
+        build_rec(left, start, depth + 1, builder, nodes);
+        let right_index = nodes.len();
+        build_rec(right, start + mid, depth + 1, builder, nodes);
+        nodes[node_index].offset = right_index as u32;
+        nodes[node_index].count = 0;
+        nodes[node_index].axis = axis as u8;
+    }
+}
+
+/// Splits on the median of the box minimums along an axis cycling with the
+/// depth, leaving everything below depth 6 in a single leaf.
+fn median_split(items: &mut [Item], depth: usize) -> Option<(usize, usize)> {
+    if depth >= 6 {
+        return None;
+    }
+    Some(split_at_median(items, depth % 3))
+}
+
+fn split_at_median(items: &mut [Item], axis: usize) -> (usize, usize) {
+    let sort_key = |x: &Item| x.bounding_box.minimum.0[axis];
+    items.sort_unstable_by(|a, b| sort_key(a).partial_cmp(&sort_key(b)).unwrap());
+    (axis, items.len() / 2)
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    count: usize,
+    bounding_box: Option<BoundingBox>,
+}
+
+struct Split {
+    axis: usize,
+    bin: usize,
+    cost: f64,
+}
+
+fn sah_split(
+    items: &mut [Item],
+    bounding_box: BoundingBox,
+    opts: &SahOptions,
+) -> Option<(usize, usize)> {
+    let centroid_bounds = BoundingBox {
+        minimum: items
+            .iter()
+            .fold(V3([f64::INFINITY; 3]), |a, b| a.min(b.centroid)),
+        maximum: items
+            .iter()
+            .fold(V3([f64::NEG_INFINITY; 3]), |a, b| a.max(b.centroid)),
+    };
+    let split = find_sah_split(items, bounding_box, centroid_bounds, opts)?;
+    let leaf_cost = opts.intersection_cost * items.len() as f64;
+    if items.len() <= opts.max_leaf_size && split.cost >= leaf_cost {
+        return None;
+    }
+
+    let is_left = |item: &Item| bin_index(item, centroid_bounds, split.axis, opts.bins) < split.bin;
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if is_left(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some((split.axis, mid))
+}
+
+/// Finds the cheapest split between bins over all axes, if any split leaves
+/// both sides non-empty.
+fn find_sah_split(
+    items: &[Item],
+    bounding_box: BoundingBox,
+    centroid_bounds: BoundingBox,
+    opts: &SahOptions,
+) -> Option<Split> {
+    let parent_area = bounding_box.surface_area();
+    let mut best: Option<Split> = None;
+
+    for axis in 0..3 {
+        let extent = centroid_bounds.maximum.0[axis] - centroid_bounds.minimum.0[axis];
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut bins = vec![
+            Bin {
+                count: 0,
+                bounding_box: None,
+            };
+            opts.bins
+        ];
+        for item in items {
+            let bin = &mut bins[bin_index(item, centroid_bounds, axis, opts.bins)];
+            bin.count += 1;
+            bin.bounding_box = Some(union_with(bin.bounding_box, item.bounding_box));
+        }
+
+        // Sweep from the right first so that every split can read the cost
+        // of everything on its right side.
+        let mut right_costs = vec![0.0; opts.bins];
+        let mut right_count = 0;
+        let mut right_box = None;
+        for bin in (1..opts.bins).rev() {
+            right_count += bins[bin].count;
+            right_box = union_option(right_box, bins[bin].bounding_box);
+            right_costs[bin] =
+                right_count as f64 * right_box.map_or(0.0, BoundingBox::surface_area);
+        }
+
+        let mut left_count = 0;
+        let mut left_box = None;
+        for bin in 1..opts.bins {
+            left_count += bins[bin - 1].count;
+            left_box = union_option(left_box, bins[bin - 1].bounding_box);
+            if left_count == 0 || left_count == items.len() {
+                continue;
+            }
+            let left_cost = left_count as f64 * left_box.map_or(0.0, BoundingBox::surface_area);
+            let cost = opts.traversal_cost
+                + opts.intersection_cost * (left_cost + right_costs[bin]) / parent_area;
+            if cost < best.as_ref().map_or(f64::INFINITY, |best| best.cost) {
+                best = Some(Split { axis, bin, cost });
             }
         }
     }
+    best
+}
+
+fn bin_index(item: &Item, centroid_bounds: BoundingBox, axis: usize, bins: usize) -> usize {
+    let V3(minimum) = centroid_bounds.minimum;
+    let V3(maximum) = centroid_bounds.maximum;
+    let offset = (item.centroid.0[axis] - minimum[axis]) / (maximum[axis] - minimum[axis]);
+    ((offset * bins as f64) as usize).min(bins - 1)
+}
+
+fn union_with(a: Option<BoundingBox>, b: BoundingBox) -> BoundingBox {
+    a.map_or(b, |a| a.union(b))
+}
+
+fn union_option(a: Option<BoundingBox>, b: Option<BoundingBox>) -> Option<BoundingBox> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.union(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
 }
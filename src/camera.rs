@@ -1,4 +1,5 @@
-use rand::thread_rng;
+use rand::rngs::SmallRng;
+use rand::Rng;
 use rand_distr::{Distribution, UnitDisc};
 
 use crate::ray::Ray;
@@ -13,6 +14,8 @@ pub struct Camera {
     viewport_height: f64,
     focus_distance: f64,
     lens_radius: f64,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 #[derive(Clone, Copy)]
@@ -22,6 +25,10 @@ pub struct CameraOptions {
     pub vertical_field_of_view: f64,
     pub aperture: f64,
     pub focus_distance: Option<f64>,
+    /// Times the shutter opens and closes, between which rays are cast at
+    /// random for motion blur.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
 }
 
 impl Camera {
@@ -43,12 +50,15 @@ impl Camera {
             viewport_height,
             focus_distance,
             lens_radius: opts.aperture / 2.0,
+            shutter_open: opts.shutter_open,
+            shutter_close: opts.shutter_close,
         }
     }
 
-    pub fn ray_from(self, normalized_x: f64, normalized_y: f64) -> Ray {
-        let [random_x, random_y]: [f64; 2] = UnitDisc.sample(&mut thread_rng());
+    pub fn ray_from(self, normalized_x: f64, normalized_y: f64, rng: &mut SmallRng) -> Ray {
+        let [random_x, random_y]: [f64; 2] = UnitDisc.sample(rng);
         let offset = self.lens_radius * (random_x * self.x_unit + random_y * self.y_unit);
+        let time = self.shutter_open + (self.shutter_close - self.shutter_open) * rng.gen::<f64>();
         Ray {
             origin: self.origin + offset,
             direction: self.focus_distance
@@ -56,6 +66,7 @@ impl Camera {
                     * (normalized_x * self.x_unit + normalized_y * self.y_unit)
                     - self.z_unit)
                 - offset,
+            time,
         }
     }
 }
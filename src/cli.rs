@@ -0,0 +1,211 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use raytracer::bounding_box_tree::{Builder, SahOptions};
+use raytracer::exr::{self, PixelType};
+use raytracer::render::Sampling;
+
+pub const USAGE: &str = "\
+Usage: raytracer [OPTIONS]
+
+Options:
+  --scene <PATH>      Scene file to render [default: built-in random spheres]
+  --width <PIXELS>    Image width [default: 1280]
+  --height <PIXELS>   Image height [default: 720]
+  --samples <N>       Samples per pixel [default: 16]
+  --depth <N>         Maximum number of scatterings per path [default: 16]
+  --output <PATH>     Output image [default: output.png]
+  --format <FORMAT>   Output format: png, exr (half float), exr-float, hdr
+                      [default: from the output extension]
+  --threads <N>       Number of render threads [default: one per CPU]
+  --seed <N>          Seed for random sampling and the built-in scene [default: 0]
+  --bvh <BUILDER>     Bounding box tree builder: sah, median [default: sah]
+  --sampling <MODE>   How lights are found: mis, material, lights [default: mis]
+  -h, --help          Print this help";
+
+#[derive(Clone, Copy)]
+pub enum OutputFormat {
+    Png,
+    Exr(PixelType),
+    Hdr,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(OutputFormat::Png),
+            "exr" => Some(OutputFormat::Exr(PixelType::Half)),
+            "exr-float" => Some(OutputFormat::Exr(PixelType::Float)),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    /// Largest width and height the writer can store.
+    fn max_size(self) -> (usize, usize) {
+        match self {
+            // PNG limits both to 2^31 - 1.
+            OutputFormat::Png => (i32::MAX as usize, i32::MAX as usize),
+            OutputFormat::Exr(pixel_type) => exr::max_size(pixel_type),
+            OutputFormat::Hdr => (usize::MAX, usize::MAX),
+        }
+    }
+}
+
+pub struct Args {
+    pub scene: Option<PathBuf>,
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: i32,
+    pub max_scatter_depth: i32,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    pub threads: Option<usize>,
+    pub seed: u64,
+    pub builder: Builder,
+    pub sampling: Sampling,
+}
+
+pub enum ParseResult {
+    Run(Args),
+    Help,
+}
+
+#[derive(Debug)]
+pub struct ArgsError(String);
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<ParseResult, ArgsError> {
+    let mut scene = None;
+    let mut width = 1280;
+    let mut height = 720;
+    let mut samples_per_pixel = 16;
+    let mut max_scatter_depth = 16;
+    let mut output = PathBuf::from("output.png");
+    let mut format = None;
+    let mut threads = None;
+    let mut seed = 0;
+    let mut builder = Builder::Sah(SahOptions::default());
+    let mut sampling = Sampling::MultipleImportance;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| ArgsError(format!("{} needs a value", arg)))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(ParseResult::Help),
+            "--scene" => scene = Some(PathBuf::from(value()?)),
+            "--width" => width = parse_positive(&arg, &value()?)?,
+            "--height" => height = parse_positive(&arg, &value()?)?,
+            "--samples" => samples_per_pixel = parse_positive(&arg, &value()?)?,
+            "--depth" => max_scatter_depth = parse_positive(&arg, &value()?)?,
+            "--output" => output = PathBuf::from(value()?),
+            "--format" => {
+                let name = value()?;
+                format = Some(
+                    OutputFormat::from_name(&name)
+                        .ok_or_else(|| ArgsError(format!("--format: unknown format '{}'", name)))?,
+                )
+            }
+            "--threads" => threads = Some(parse_positive(&arg, &value()?)?),
+            "--seed" => seed = parse_number(&arg, &value()?)?,
+            "--bvh" => {
+                builder = match value()?.as_str() {
+                    "sah" => Builder::Sah(SahOptions::default()),
+                    "median" => Builder::Median,
+                    other => return Err(ArgsError(format!("--bvh: unknown builder '{}'", other))),
+                }
+            }
+            "--sampling" => {
+                sampling = match value()?.as_str() {
+                    "mis" => Sampling::MultipleImportance,
+                    "material" => Sampling::Material,
+                    "lights" => Sampling::Lights,
+                    other => {
+                        return Err(ArgsError(format!("--sampling: unknown mode '{}'", other)))
+                    }
+                }
+            }
+            _ => return Err(ArgsError(format!("unknown argument '{}'", arg))),
+        }
+    }
+
+    let format = match format {
+        Some(format) => format,
+        None => format_from_extension(&output)?,
+    };
+    let (max_width, max_height) = format.max_size();
+    if width > max_width {
+        return Err(ArgsError(format!(
+            "--width: at most {} for this format, got {}",
+            max_width, width
+        )));
+    }
+    if height > max_height {
+        return Err(ArgsError(format!(
+            "--height: at most {} for this format, got {}",
+            max_height, height
+        )));
+    }
+    if width.checked_mul(height).is_none() {
+        return Err(ArgsError(format!(
+            "image of {}x{} pixels is too large",
+            width, height
+        )));
+    }
+
+    Ok(ParseResult::Run(Args {
+        scene,
+        width,
+        height,
+        samples_per_pixel,
+        max_scatter_depth,
+        output,
+        format,
+        threads,
+        seed,
+        builder,
+        sampling,
+    }))
+}
+
+fn format_from_extension(path: &Path) -> Result<OutputFormat, ArgsError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    OutputFormat::from_name(extension).ok_or_else(|| {
+        ArgsError(format!(
+            "cannot tell the format of '{}' from its extension, use --format",
+            path.display()
+        ))
+    })
+}
+
+fn parse_number<T: FromStr>(arg: &str, value: &str) -> Result<T, ArgsError> {
+    value
+        .parse()
+        .map_err(|_| ArgsError(format!("{}: invalid number '{}'", arg, value)))
+}
+
+fn parse_positive<T: FromStr + PartialOrd + Default>(
+    arg: &str,
+    value: &str,
+) -> Result<T, ArgsError> {
+    let number = parse_number(arg, value)?;
+    if number > T::default() {
+        Ok(number)
+    } else {
+        Err(ArgsError(format!(
+            "{}: must be positive, got {}",
+            arg, value
+        )))
+    }
+}
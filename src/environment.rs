@@ -0,0 +1,215 @@
+use std::f64::consts::PI;
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+use crate::hdr::HdrImage;
+use crate::util::luminance;
+use crate::v3::V3;
+
+/// Light arriving from infinitely far away in the directions rays escape to.
+pub trait Environment: Send + Sync {
+    fn radiance(&self, direction: V3) -> V3;
+
+    /// Radiance seen by camera rays and specular scattering, which can show
+    /// features such as a sun disk that are otherwise lit by a separate light.
+    fn visible_radiance(&self, direction: V3) -> V3 {
+        self.radiance(direction)
+    }
+
+    /// Whether `random_direction` favors the brighter directions, so that
+    /// the environment is worth sampling directly as a light.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Density per unit solid angle of `random_direction` picking `direction`.
+    fn pdf_value(&self, _direction: V3) -> f64 {
+        0.0
+    }
+
+    fn random_direction(&self, _rng: &mut SmallRng) -> V3 {
+        V3::ZERO
+    }
+}
+
+/// Blends between two colors by how far up a direction points.
+pub struct Gradient {
+    pub up: V3,
+    pub down: V3,
+}
+
+impl Default for Gradient {
+    /// A light blue sky fading to white.
+    fn default() -> Gradient {
+        Gradient {
+            up: V3([0.5, 0.7, 1.0]),
+            down: V3([1.0, 1.0, 1.0]),
+        }
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: V3) -> V3 {
+        let t = 0.5 * (direction.normalize().y() + 1.0);
+        self.down * (1.0 - t) + self.up * t
+    }
+}
+
+pub struct Constant {
+    pub color: V3,
+}
+
+impl Environment for Constant {
+    fn radiance(&self, _direction: V3) -> V3 {
+        self.color
+    }
+}
+
+/// Equirectangular image around the y axis, with the top row straight up,
+/// importance sampled by luminance.
+pub struct EnvironmentMap {
+    image: HdrImage,
+    /// Angle the image is turned by around the y axis, in radians.
+    rotation: f64,
+    /// Density of picking each pixel, proportional to its luminance times
+    /// the solid angle it covers, over the image as a unit square.
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: HdrImage, rotation: f64) -> EnvironmentMap {
+        let mut weights = Vec::with_capacity(image.pixels.len());
+        for (y, row) in image.pixels.chunks(image.width).enumerate() {
+            let sin_theta = (PI * (y as f64 + 0.5) / image.height as f64).sin();
+            weights.extend(row.iter().map(|&pixel| luminance(pixel) * sin_theta));
+        }
+        let distribution = Distribution2D::new(&weights, image.width);
+        EnvironmentMap {
+            image,
+            rotation,
+            distribution,
+        }
+    }
+
+    /// Coordinates in the unit square of the image for a direction.
+    fn uv(&self, direction: V3) -> [f64; 2] {
+        let V3([x, y, z]) = direction.normalize();
+        let phi = z.atan2(x) + self.rotation;
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = y.clamp(-1.0, 1.0).acos() / PI;
+        [u, v]
+    }
+
+    fn direction(&self, [u, v]: [f64; 2]) -> V3 {
+        let phi = 2.0 * PI * u - self.rotation;
+        let theta = PI * v;
+        V3([
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        ])
+    }
+
+    fn pixel_index(&self, [u, v]: [f64; 2]) -> usize {
+        let x = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+        x + y * self.image.width
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: V3) -> V3 {
+        self.image.pixels[self.pixel_index(self.uv(direction))]
+    }
+
+    fn is_light(&self) -> bool {
+        self.distribution.total > 0.0
+    }
+
+    fn pdf_value(&self, direction: V3) -> f64 {
+        let uv = self.uv(direction);
+        let sin_theta = (PI * uv[1]).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // The mapping stretches the unit square over 2π² steradians, less
+        // towards the poles.
+        self.distribution.pdf(self.pixel_index(uv)) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random_direction(&self, rng: &mut SmallRng) -> V3 {
+        self.direction(self.distribution.sample(rng))
+    }
+}
+
+/// Piecewise constant density over a grid of cells covering the unit square,
+/// sampled by picking a row from the marginal distribution and then a column
+/// from that row's distribution.
+struct Distribution2D {
+    width: usize,
+    weights: Vec<f64>,
+    /// Running sums of the weights within each row, `width + 1` per row.
+    row_cdfs: Vec<f64>,
+    /// Running sums of the row totals.
+    marginal_cdf: Vec<f64>,
+    /// Mean weight over all cells.
+    total: f64,
+}
+
+impl Distribution2D {
+    fn new(weights: &[f64], width: usize) -> Distribution2D {
+        let height = weights.len() / width;
+        let mut row_cdfs = Vec::with_capacity(height * (width + 1));
+        let mut marginal_cdf = Vec::with_capacity(height + 1);
+        marginal_cdf.push(0.0);
+        for row in weights.chunks(width) {
+            let mut sum = 0.0;
+            row_cdfs.push(sum);
+            for &weight in row {
+                sum += weight;
+                row_cdfs.push(sum);
+            }
+            marginal_cdf.push(marginal_cdf.last().unwrap() + sum);
+        }
+        let total = marginal_cdf.last().unwrap() / weights.len() as f64;
+        Distribution2D {
+            width,
+            weights: weights.to_vec(),
+            row_cdfs,
+            marginal_cdf,
+            total,
+        }
+    }
+
+    fn pdf(&self, index: usize) -> f64 {
+        self.weights[index] / self.total
+    }
+
+    fn sample(&self, rng: &mut SmallRng) -> [f64; 2] {
+        let height = self.marginal_cdf.len() - 1;
+        let (y, v_offset) = sample_cdf(&self.marginal_cdf, rng.gen());
+        let row_cdf = &self.row_cdfs[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let (x, u_offset) = sample_cdf(row_cdf, rng.gen());
+        [
+            (x as f64 + u_offset) / self.width as f64,
+            (y as f64 + v_offset) / height as f64,
+        ]
+    }
+}
+
+/// Finds the cell whose share of the running sums `cdf` contains the
+/// fraction `r` of the total, and where within the cell it lies.
+fn sample_cdf(cdf: &[f64], r: f64) -> (usize, f64) {
+    let target = r * cdf[cdf.len() - 1];
+    let index = cdf[1..]
+        .partition_point(|&sum| sum <= target)
+        .min(cdf.len() - 2);
+    let width = cdf[index + 1] - cdf[index];
+    let offset = if width > 0.0 {
+        (target - cdf[index]) / width
+    } else {
+        0.5
+    };
+    (index, offset)
+}
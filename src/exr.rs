@@ -0,0 +1,175 @@
+//! Writer for uncompressed scanline OpenEXR images with RGB channels.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::v3::V3;
+
+#[derive(Clone, Copy)]
+pub enum PixelType {
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn code(self) -> i32 {
+        match self {
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Float => 4,
+        }
+    }
+}
+
+/// Largest width and height whose data window and scanline sizes fit into
+/// the `i32` fields of the file.
+pub fn max_size(pixel_type: PixelType) -> (usize, usize) {
+    let max = i32::MAX as usize;
+    (max / (3 * pixel_type.size()), max)
+}
+
+pub fn write(
+    path: &Path,
+    width: usize,
+    height: usize,
+    pixels: &[V3],
+    pixel_type: PixelType,
+) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    encode(&mut writer, width, height, pixels, pixel_type)?;
+    writer.flush()
+}
+
+pub fn encode<W: Write>(
+    writer: &mut W,
+    width: usize,
+    height: usize,
+    pixels: &[V3],
+    pixel_type: PixelType,
+) -> io::Result<()> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    // Channels have to be listed in alphabetical order.
+    let mut channels = Vec::new();
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&pixel_type.code().to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    push_attribute(&mut header, "channels", "chlist", &channels);
+
+    push_attribute(&mut header, "compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect();
+    push_attribute(&mut header, "dataWindow", "box2i", &window);
+    push_attribute(&mut header, "displayWindow", "box2i", &window);
+    push_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    push_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    push_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    push_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    writer.write_all(&header)?;
+
+    let line_size = 3 * width * pixel_type.size();
+    let offset_table_size = 8 * height;
+    for y in 0..height {
+        let offset = header.len() + offset_table_size + y * (8 + line_size);
+        writer.write_all(&(offset as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(8 + line_size);
+    for (y, row) in pixels.chunks(width).enumerate() {
+        line.clear();
+        line.extend_from_slice(&(y as i32).to_le_bytes());
+        line.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for channel in [2, 1, 0] {
+            for pixel in row {
+                let value = pixel.0[channel] as f32;
+                match pixel_type {
+                    PixelType::Half => line.extend_from_slice(&f32_to_f16(value).to_le_bytes()),
+                    PixelType::Float => line.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+fn push_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Converts to IEEE 754 half precision, rounding to nearest even and
+/// saturating to infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        // Subnormal: shift in the implicit leading bit.
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let rounded = round_shift(mantissa, shift);
+        return sign | rounded as u16;
+    }
+
+    let rounded = round_shift(mantissa, 13) + ((half_exponent as u32) << 10);
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity.
+    sign | rounded.min(0x7c00) as u16
+}
+
+fn round_shift(value: u32, shift: u32) -> u32 {
+    let halfway = 1 << (shift - 1);
+    let remainder = value & ((1 << shift) - 1);
+    let mut result = value >> shift;
+    if remainder > halfway || (remainder == halfway && result & 1 == 1) {
+        result += 1;
+    }
+    result
+}
@@ -0,0 +1,112 @@
+//! Reader for dense voxel grids of one value per voxel, such as the density
+//! or temperature channel of a smoke simulation.
+//!
+//! A grid file holds the resolution along x, y and z as three little-endian
+//! `u32`s, followed by one little-endian `f32` per voxel with x varying
+//! fastest and z slowest.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::v3::V3;
+
+pub struct Grid {
+    pub resolution: [usize; 3],
+    pub values: Vec<f32>,
+}
+
+impl Grid {
+    /// Trilinearly interpolated value at `point` in the unit cube the grid
+    /// spans, with voxel centers as sample positions.
+    pub fn value(&self, point: V3) -> f64 {
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut fraction = [0.0; 3];
+        for i in 0..3 {
+            let last = self.resolution[i] - 1;
+            let x = (point.0[i] * self.resolution[i] as f64 - 0.5).clamp(0.0, last as f64);
+            lower[i] = x as usize;
+            upper[i] = (lower[i] + 1).min(last);
+            fraction[i] = x - lower[i] as f64;
+        }
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for i in 0..3 {
+                if corner >> i & 1 == 1 {
+                    index[i] = upper[i];
+                    weight *= fraction[i];
+                } else {
+                    index[i] = lower[i];
+                    weight *= 1.0 - fraction[i];
+                }
+            }
+            value += weight * self.voxel(index);
+        }
+        value
+    }
+
+    pub fn voxel(&self, [x, y, z]: [usize; 3]) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[x + nx * (y + ny * z)] as f64
+    }
+
+    /// Largest voxel value, which no interpolated value exceeds.
+    pub fn max(&self) -> f64 {
+        self.values
+            .iter()
+            .fold(0.0f32, |max, &value| max.max(value)) as f64
+    }
+}
+
+pub fn load(path: &Path) -> io::Result<Grid> {
+    let file = File::open(path)?;
+    decode(&mut BufReader::new(file))
+}
+
+pub fn decode<R: Read>(reader: &mut R) -> io::Result<Grid> {
+    let mut resolution = [0; 3];
+    for size in &mut resolution {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        *size = u32::from_le_bytes(bytes) as usize;
+    }
+    if resolution.contains(&0) {
+        return Err(invalid_data("empty grid"));
+    }
+    let count = resolution
+        .iter()
+        .try_fold(4usize, |count, &size| count.checked_mul(size))
+        .ok_or_else(|| invalid_data("grid too large"))?
+        / 4;
+
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() != count * 4 {
+        return Err(invalid_data(format!(
+            "expected {} values for a {}x{}x{} grid, got {} bytes",
+            count,
+            resolution[0],
+            resolution[1],
+            resolution[2],
+            bytes.len()
+        )));
+    }
+    let values = bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect::<Vec<_>>();
+    if values
+        .iter()
+        .any(|value| !value.is_finite() || *value < 0.0)
+    {
+        return Err(invalid_data("values must be finite and not negative"));
+    }
+    Ok(Grid { resolution, values })
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
@@ -0,0 +1,278 @@
+//! Reader and writer for Radiance RGBE (`.hdr`) images.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::v3::V3;
+
+pub fn write(path: &Path, width: usize, height: usize, pixels: &[V3]) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    encode(&mut writer, width, height, pixels)?;
+    writer.flush()
+}
+
+pub fn encode<W: Write>(
+    writer: &mut W,
+    width: usize,
+    height: usize,
+    pixels: &[V3],
+) -> io::Result<()> {
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    let mut channel_bytes = Vec::with_capacity(width);
+    for row in pixels.chunks(width) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(|&pixel| to_rgbe(pixel)).collect();
+        // Run-length encoding is only defined for these widths; outside of it
+        // scanlines are stored flat.
+        if !(8..0x8000).contains(&width) {
+            for pixel in rgbe {
+                writer.write_all(&pixel)?;
+            }
+            continue;
+        }
+
+        writer.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+        for channel in 0..4 {
+            channel_bytes.clear();
+            channel_bytes.extend(rgbe.iter().map(|pixel| pixel[channel]));
+            write_run_length_encoded(writer, &channel_bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Shared exponent encoding: the largest component's exponent is stored in
+/// the fourth byte and all three mantissas are scaled by it.
+fn to_rgbe(color: V3) -> [u8; 4] {
+    let V3([r, g, b]) = color.map(|x| x.max(0.0));
+    let maximum = r.max(g).max(b);
+    if maximum < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let exponent = maximum.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f64.powi(exponent);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+/// Encodes one channel of a scanline as runs of a repeated byte (count above
+/// 128) and literal spans (count up to 128).
+fn write_run_length_encoded<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    const MIN_RUN: usize = 4;
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let mut run = 1;
+        while i + run < bytes.len() && run < 127 && bytes[i + run] == bytes[i] {
+            run += 1;
+        }
+        if run >= MIN_RUN {
+            write_literals(writer, &bytes[literal_start..i])?;
+            writer.write_all(&[128 + run as u8, bytes[i]])?;
+            i += run;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    write_literals(writer, &bytes[literal_start..])
+}
+
+fn write_literals<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    for chunk in bytes.chunks(128) {
+        writer.write_all(&[chunk.len() as u8])?;
+        writer.write_all(chunk)?;
+    }
+    Ok(())
+}
+
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    /// Linear colors, row by row from the top.
+    pub pixels: Vec<V3>,
+}
+
+pub fn load(path: &Path) -> io::Result<HdrImage> {
+    let file = File::open(path)?;
+    decode(&mut BufReader::new(file))
+}
+
+/// Largest width or height accepted, which keeps a corrupt header from
+/// asking for an absurdly large scanline buffer.
+const MAX_DIMENSION: usize = 1 << 20;
+
+/// Decodes images in the usual top-to-bottom, left-to-right orientation with
+/// flat, old-style or new-style run-length encoded scanlines.
+pub fn decode<R: BufRead>(reader: &mut R) -> io::Result<HdrImage> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("missing #? signature"));
+    }
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("missing resolution line"));
+        }
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(format) = header.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(format!("unsupported format {}", format)));
+            }
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let (height, width): (usize, usize) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => match (height.parse(), width.parse()) {
+            (Ok(height), Ok(width)) => (height, width),
+            _ => return Err(invalid_data("invalid resolution")),
+        },
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported orientation '{}'",
+                line.trim()
+            )))
+        }
+    };
+
+    if width == 0 || height == 0 {
+        return Err(invalid_data("empty image"));
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION || width.checked_mul(height).is_none() {
+        return Err(invalid_data(format!(
+            "image too large: {}x{}",
+            width, height
+        )));
+    }
+
+    // The header is not trusted to size buffers, so the pixels grow only as
+    // scanlines are actually read.
+    let mut pixels = Vec::new();
+    let mut scanline = vec![[0; 4]; width];
+    for _ in 0..height {
+        read_scanline(reader, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| from_rgbe(rgbe)));
+    }
+    Ok(HdrImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn read_scanline<R: Read>(reader: &mut R, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    if width == 0 {
+        return Ok(());
+    }
+    let mut first = [0; 4];
+    reader.read_exact(&mut first)?;
+    let is_new_style = (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2;
+    if !is_new_style {
+        return read_old_style_scanline(reader, first, scanline);
+    }
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err(invalid_data("scanline width mismatch"));
+    }
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0];
+            reader.read_exact(&mut count)?;
+            let (count, is_run) = if count[0] > 128 {
+                (count[0] as usize - 128, true)
+            } else {
+                (count[0] as usize, false)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("bad scanline run length"));
+            }
+            if is_run {
+                let mut value = [0];
+                reader.read_exact(&mut value)?;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value[0];
+                }
+            } else {
+                let mut values = [0; 128];
+                reader.read_exact(&mut values[..count])?;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(&values[..count]) {
+                    pixel[channel] = value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+/// Reads pixels stored one after another, where a pixel of `1, 1, 1, n`
+/// repeats the previous pixel `n` times, shifted by 8 bits per consecutive
+/// repeat marker.
+fn read_old_style_scanline<R: Read>(
+    reader: &mut R,
+    first: [u8; 4],
+    scanline: &mut [[u8; 4]],
+) -> io::Result<()> {
+    let mut pixel = first;
+    let mut x = 0;
+    let mut shift = 0;
+    loop {
+        if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 {
+            if x == 0 {
+                return Err(invalid_data("repeat marker at the start of a scanline"));
+            }
+            let count = (pixel[3] as usize) << shift;
+            if x + count > scanline.len() {
+                return Err(invalid_data("bad scanline run length"));
+            }
+            let previous = scanline[x - 1];
+            for repeated in &mut scanline[x..x + count] {
+                *repeated = previous;
+            }
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = pixel;
+            x += 1;
+            shift = 0;
+        }
+        if x == scanline.len() {
+            return Ok(());
+        }
+        reader.read_exact(&mut pixel)?;
+    }
+}
+
+fn from_rgbe([r, g, b, e]: [u8; 4]) -> V3 {
+    if e == 0 {
+        return V3::ZERO;
+    }
+    let scale = 2f64.powi(e as i32 - 136);
+    V3([
+        (r as f64 + 0.5) * scale,
+        (g as f64 + 0.5) * scale,
+        (b as f64 + 0.5) * scale,
+    ])
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
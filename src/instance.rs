@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+
+use crate::bounding_box::BoundingBox;
+use crate::bounding_box_tree::BoundingBoxTree;
+use crate::m4::{Motion, Transform};
+use crate::ray::Ray;
+use crate::surface::{RayHitMaterial, Surface};
+
+/// Places a shared surface in the world with a transform, so that one mesh
+/// can appear many times without copying its geometry.
+///
+/// Lights inside an instance are only found by scattered rays and never
+/// sampled directly.
+pub struct Instance {
+    pub surface: Arc<dyn Surface>,
+    pub transform: Transform,
+}
+
+impl Surface for Instance {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        let mut result = transformed_hit(&self.transform, ray, |object_ray| {
+            self.surface.hit(object_ray, t_min, t_max)
+        })?;
+        result.surface = self;
+        Some(result)
+    }
+
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        let mut result = transformed_hit(&self.transform, ray, |object_ray| {
+            self.surface.sample_hit(object_ray, t_min, t_max, rng)
+        })?;
+        result.surface = self;
+        Some(result)
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        self.transform
+            .bounding_box(self.surface.calculate_bounding_box())
+    }
+
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, rng: &mut SmallRng) -> f64 {
+        let object_ray = object_ray(&self.transform, ray);
+        self.surface.transmittance(object_ray, t_min, t_max, rng)
+    }
+}
+
+/// Instance whose transform follows `motion` over the time of each ray.
+pub struct MovingInstance {
+    pub surface: Arc<dyn Surface>,
+    pub motion: Motion,
+}
+
+impl Surface for MovingInstance {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        let transform = self.motion.at(ray.time).transform();
+        let mut result = transformed_hit(&transform, ray, |object_ray| {
+            self.surface.hit(object_ray, t_min, t_max)
+        })?;
+        result.surface = self;
+        Some(result)
+    }
+
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        let transform = self.motion.at(ray.time).transform();
+        let mut result = transformed_hit(&transform, ray, |object_ray| {
+            self.surface.sample_hit(object_ray, t_min, t_max, rng)
+        })?;
+        result.surface = self;
+        Some(result)
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        self.motion
+            .bounding_box(self.surface.calculate_bounding_box())
+    }
+
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, rng: &mut SmallRng) -> f64 {
+        let object_ray = object_ray(&self.motion.at(ray.time).transform(), ray);
+        self.surface.transmittance(object_ray, t_min, t_max, rng)
+    }
+}
+
+/// Hit found by `hit` for `ray` taken into object space, brought back into
+/// world space.
+fn transformed_hit<'s>(
+    transform: &Transform,
+    ray: Ray,
+    hit: impl FnOnce(Ray) -> Option<RayHitMaterial<'s>>,
+) -> Option<RayHitMaterial<'s>> {
+    let mut result = hit(object_ray(transform, ray))?;
+    result.hit.position = ray.at(result.hit.t);
+    result.hit.normal = transform.normal(result.hit.normal).normalize();
+    result.hit.tangent = transform.vector(result.hit.tangent);
+    Some(result)
+}
+
+/// `ray` in the space of the instanced surface. The direction is left
+/// unnormalized so that distances along the ray are the same in both spaces.
+fn object_ray(transform: &Transform, ray: Ray) -> Ray {
+    Ray {
+        origin: transform.inverse.transform_point(ray.origin),
+        direction: transform.inverse.transform_vector(ray.direction),
+        time: ray.time,
+    }
+}
+
+/// Where an instance of the object with index `object` goes.
+#[derive(Clone, Copy)]
+pub struct Placement {
+    pub object: usize,
+    pub transform: Transform,
+    /// Movement over time that takes the place of `transform`, if any.
+    pub motion: Option<Motion>,
+}
+
+/// Instances of the object trees for each placement. Moving instances only
+/// takes placing the same trees again and rebuilding the tree above them.
+pub fn place(objects: &[Arc<BoundingBoxTree>], placements: &[Placement]) -> Vec<Box<dyn Surface>> {
+    placements
+        .iter()
+        .map(|placement| {
+            let surface = objects[placement.object].clone();
+            match placement.motion {
+                Some(motion) => Box::new(MovingInstance { surface, motion }) as Box<dyn Surface>,
+                None => Box::new(Instance {
+                    surface,
+                    transform: placement.transform,
+                }),
+            }
+        })
+        .collect()
+}
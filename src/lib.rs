@@ -0,0 +1,27 @@
+pub mod bounding_box;
+pub mod bounding_box_tree;
+pub mod camera;
+pub mod environment;
+pub mod exr;
+pub mod grid;
+pub mod hdr;
+pub mod instance;
+pub mod light;
+pub mod m4;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod microfacet;
+pub mod obj;
+pub mod perlin;
+pub mod principled;
+pub mod ray;
+pub mod ray_hit;
+pub mod render;
+pub mod scene;
+pub mod scene_file;
+pub mod sky;
+pub mod surface;
+pub mod texture;
+pub mod util;
+pub mod v3;
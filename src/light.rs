@@ -0,0 +1,92 @@
+use crate::v3::V3;
+
+/// Light arriving at a point from a single direction.
+#[derive(Clone, Copy)]
+pub struct LightSample {
+    /// Unit direction from the lit point towards the light.
+    pub direction: V3,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: f64,
+    /// Irradiance on a surface facing the light.
+    pub irradiance: V3,
+}
+
+/// Light source concentrated in a point or a direction, so that it can only
+/// be found by sampling it and never by a scattered ray.
+pub trait Light: Send + Sync {
+    fn sample(&self, position: V3) -> Option<LightSample>;
+}
+
+/// Emits `intensity` in all directions, falling off with the squared distance.
+pub struct PointLight {
+    pub position: V3,
+    pub intensity: V3,
+}
+
+impl Light for PointLight {
+    fn sample(&self, position: V3) -> Option<LightSample> {
+        let offset = self.position - position;
+        let distance_squared = offset.length_squared();
+        let distance = distance_squared.sqrt();
+        Some(LightSample {
+            direction: offset * (1.0 / distance),
+            distance,
+            irradiance: self.intensity * (1.0 / distance_squared),
+        })
+    }
+}
+
+/// Point light restricted to a cone around `direction`, fading out smoothly
+/// between `falloff_angle` and `cone_angle` from its axis.
+pub struct SpotLight {
+    pub position: V3,
+    pub direction: V3,
+    pub intensity: V3,
+    /// Angle from the axis beyond which nothing is emitted, in radians.
+    pub cone_angle: f64,
+    /// Angle from the axis where the intensity starts to fall off, in radians.
+    pub falloff_angle: f64,
+}
+
+impl Light for SpotLight {
+    fn sample(&self, position: V3) -> Option<LightSample> {
+        let offset = self.position - position;
+        let distance_squared = offset.length_squared();
+        let distance = distance_squared.sqrt();
+        let direction = offset * (1.0 / distance);
+
+        let cos = -direction.dot(self.direction.normalize());
+        let cos_cone = self.cone_angle.cos();
+        let cos_falloff = self.falloff_angle.cos();
+        let falloff = if cos >= cos_falloff {
+            1.0
+        } else if cos <= cos_cone {
+            return None;
+        } else {
+            let t = (cos - cos_cone) / (cos_falloff - cos_cone);
+            t * t * (3.0 - 2.0 * t)
+        };
+        Some(LightSample {
+            direction,
+            distance,
+            irradiance: self.intensity * (falloff / distance_squared),
+        })
+    }
+}
+
+/// Parallel light travelling along `direction` from infinitely far away,
+/// like sunlight.
+pub struct DirectionalLight {
+    pub direction: V3,
+    pub irradiance: V3,
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _position: V3) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction.normalize(),
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
@@ -0,0 +1,322 @@
+use std::ops::Mul;
+
+use crate::bounding_box::BoundingBox;
+use crate::v3::V3;
+
+/// 4x4 matrix stored row by row, acting on column vectors.
+#[derive(Clone, Copy)]
+pub struct M4(pub [[f64; 4]; 4]);
+
+impl M4 {
+    pub const IDENTITY: M4 = M4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(offset: V3) -> M4 {
+        let V3([x, y, z]) = offset;
+        M4([
+            [1.0, 0.0, 0.0, x],
+            [0.0, 1.0, 0.0, y],
+            [0.0, 0.0, 1.0, z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: V3) -> M4 {
+        let V3([x, y, z]) = factors;
+        M4([
+            [x, 0.0, 0.0, 0.0],
+            [0.0, y, 0.0, 0.0],
+            [0.0, 0.0, z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Rotation by `angle` radians around `axis`, counterclockwise when
+    /// looking against the axis.
+    pub fn rotation(axis: V3, angle: f64) -> M4 {
+        let V3([x, y, z]) = axis.normalize();
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        M4([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(self) -> M4 {
+        let M4(m) = self;
+        M4([0, 1, 2, 3].map(|i| [m[0][i], m[1][i], m[2][i], m[3][i]]))
+    }
+
+    /// Inverse by Gauss–Jordan elimination with partial pivoting, or `None`
+    /// if the matrix is singular.
+    pub fn inverse(self) -> Option<M4> {
+        let M4(mut m) = self;
+        let M4(mut inverse) = M4::IDENTITY;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))
+                .unwrap();
+            if m[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / m[column][column];
+            for i in 0..4 {
+                m[column][i] *= scale;
+                inverse[column][i] *= scale;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = m[row][column];
+                for i in 0..4 {
+                    m[row][i] -= factor * m[column][i];
+                    inverse[row][i] -= factor * inverse[column][i];
+                }
+            }
+        }
+        Some(M4(inverse))
+    }
+
+    /// Applies the matrix to a position, including the translation.
+    pub fn transform_point(self, point: V3) -> V3 {
+        let M4(m) = self;
+        let V3([x, y, z]) = point;
+        let [tx, ty, tz, w] =
+            [0, 1, 2, 3].map(|i| m[i][0] * x + m[i][1] * y + m[i][2] * z + m[i][3]);
+        if w == 1.0 {
+            V3([tx, ty, tz])
+        } else {
+            V3([tx, ty, tz]) * (1.0 / w)
+        }
+    }
+
+    /// Applies the matrix to a direction or offset, ignoring the translation.
+    pub fn transform_vector(self, vector: V3) -> V3 {
+        let M4(m) = self;
+        let V3([x, y, z]) = vector;
+        V3([0, 1, 2].map(|i| m[i][0] * x + m[i][1] * y + m[i][2] * z))
+    }
+}
+
+impl Mul for M4 {
+    type Output = M4;
+
+    fn mul(self, rhs: M4) -> M4 {
+        let M4(a) = self;
+        let M4(b) = rhs;
+        M4([0, 1, 2, 3].map(|i| [0, 1, 2, 3].map(|j| (0..4).map(|k| a[i][k] * b[k][j]).sum())))
+    }
+}
+
+/// Affine transform from object to world space, kept together with its
+/// inverse so neither has to be recomputed per ray.
+#[derive(Clone, Copy)]
+pub struct Transform {
+    pub matrix: M4,
+    pub inverse: M4,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        matrix: M4::IDENTITY,
+        inverse: M4::IDENTITY,
+    };
+
+    /// Transform for an invertible `matrix`.
+    pub fn new(matrix: M4) -> Option<Transform> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn translation(offset: V3) -> Transform {
+        Transform {
+            matrix: M4::translation(offset),
+            inverse: M4::translation(-offset),
+        }
+    }
+
+    /// Scaling by nonzero `factors` along each axis.
+    pub fn scaling(factors: V3) -> Transform {
+        Transform {
+            matrix: M4::scaling(factors),
+            inverse: M4::scaling(factors.map(|x| 1.0 / x)),
+        }
+    }
+
+    pub fn rotation(axis: V3, angle: f64) -> Transform {
+        let matrix = M4::rotation(axis, angle);
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    /// Applies `self` and then `next`.
+    pub fn then(self, next: Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn point(&self, point: V3) -> V3 {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn vector(&self, vector: V3) -> V3 {
+        self.matrix.transform_vector(vector)
+    }
+
+    /// Transforms a surface normal, which takes the inverse transpose to stay
+    /// perpendicular to the surface under non-uniform scaling. The result is
+    /// not normalized.
+    pub fn normal(&self, normal: V3) -> V3 {
+        self.inverse.transpose().transform_vector(normal)
+    }
+
+    /// Smallest box containing the transformed corners of `bounding_box`.
+    pub fn bounding_box(&self, bounding_box: BoundingBox) -> BoundingBox {
+        let BoundingBox { minimum, maximum } = bounding_box;
+        let corners: Vec<V3> = (0..8)
+            .map(|i| {
+                let pick = |axis: usize| {
+                    if i & (1 << axis) == 0 {
+                        minimum.0[axis]
+                    } else {
+                        maximum.0[axis]
+                    }
+                };
+                self.point(V3([pick(0), pick(1), pick(2)]))
+            })
+            .collect();
+        BoundingBox::from_points(&corners)
+    }
+}
+
+/// Scaling, then rotation around the x, y and z axes in turn, then
+/// translation, which unlike a matrix can be interpolated.
+#[derive(Clone, Copy)]
+pub struct Pose {
+    pub scale: V3,
+    /// Angles around the x, y and z axes, in radians.
+    pub rotation: V3,
+    pub translation: V3,
+}
+
+impl Pose {
+    pub const IDENTITY: Pose = Pose {
+        scale: V3([1.0, 1.0, 1.0]),
+        rotation: V3::ZERO,
+        translation: V3::ZERO,
+    };
+
+    pub fn transform(&self) -> Transform {
+        let mut transform = Transform::scaling(self.scale);
+        for (axis, &angle) in self.rotation.0.iter().enumerate() {
+            if angle != 0.0 {
+                let mut direction = V3::ZERO;
+                direction.0[axis] = 1.0;
+                transform = transform.then(Transform::rotation(direction, angle));
+            }
+        }
+        transform.then(Transform::translation(self.translation))
+    }
+
+    /// Blends each component linearly, from `self` at `t = 0` to `other` at
+    /// `t = 1`.
+    pub fn lerp(self, other: Pose, t: f64) -> Pose {
+        let lerp = |a: V3, b: V3| a + (b - a) * t;
+        Pose {
+            scale: lerp(self.scale, other.scale),
+            rotation: lerp(self.rotation, other.rotation),
+            translation: lerp(self.translation, other.translation),
+        }
+    }
+}
+
+/// Pose changing linearly from `start` at `start_time` to `end` at
+/// `end_time`, holding still before and after. The scales at both ends must
+/// have the same sign on each axis, or the scale passes through zero.
+#[derive(Clone, Copy)]
+pub struct Motion {
+    pub start: Pose,
+    pub end: Pose,
+    pub start_time: f64,
+    pub end_time: f64,
+}
+
+impl Motion {
+    pub fn at(&self, time: f64) -> Pose {
+        if self.end_time <= self.start_time {
+            return self.start;
+        }
+        let t = ((time - self.start_time) / (self.end_time - self.start_time)).clamp(0.0, 1.0);
+        self.start.lerp(self.end, t)
+    }
+
+    /// Box containing `bounding_box` in every pose along the motion.
+    pub fn bounding_box(&self, bounding_box: BoundingBox) -> BoundingBox {
+        const STEPS: usize = 32;
+        let swept = (0..=STEPS)
+            .map(|step| {
+                let t = step as f64 / STEPS as f64;
+                let pose = self.start.lerp(self.end, t);
+                pose.transform().bounding_box(bounding_box)
+            })
+            .reduce(|a, b| a.union(b))
+            .unwrap();
+
+        // Between the steps, translation and scaling move points along
+        // straight lines, which the union covers, but rotation moves them
+        // along arcs that bulge out by at most `r (1 - cos(angle / 2))`.
+        let step_angle = (self.end.rotation - self.start.rotation)
+            .0
+            .iter()
+            .map(|angle| angle.abs())
+            .sum::<f64>()
+            / STEPS as f64;
+        let max_scale = [self.start.scale, self.end.scale]
+            .iter()
+            .flat_map(|scale| scale.0)
+            .fold(0.0, |a: f64, b| a.max(b.abs()));
+        let radius = bounding_box
+            .minimum
+            .map(f64::abs)
+            .max(bounding_box.maximum.map(f64::abs))
+            .length()
+            * max_scale;
+        let padding = V3([radius * (1.0 - (step_angle / 2.0).cos()); 3]);
+        BoundingBox {
+            minimum: swept.minimum - padding,
+            maximum: swept.maximum + padding,
+        }
+    }
+}
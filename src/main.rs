@@ -1,74 +1,137 @@
-mod bounding_box;
-mod bounding_box_tree;
-mod camera;
-mod material;
-mod ray;
-mod ray_hit;
-mod render;
-mod scene;
-mod surface;
-mod util;
-mod v3;
+mod cli;
 
+use std::env;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{self, BufWriter};
 use std::path::Path;
+use std::process;
 use std::sync::Mutex;
+use std::time::Instant;
 
-use crate::render::RenderOptions;
-use crate::scene::make_scene;
-use crate::v3::V3;
 use png::HasParameters;
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
 use rayon::prelude::*;
+use raytracer::bounding_box_tree;
+use raytracer::exr;
+use raytracer::hdr;
+use raytracer::instance;
+use raytracer::render::{self, RenderOptions};
+use raytracer::scene::make_scene;
+use raytracer::scene_file;
+use raytracer::v3::V3;
+
+use crate::cli::{OutputFormat, ParseResult};
 
 fn main() {
-    const WIDTH: usize = 1280;
-    const HEIGHT: usize = 720;
+    let args = match cli::parse(env::args().skip(1)) {
+        Ok(ParseResult::Run(args)) => args,
+        Ok(ParseResult::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\nFor more information, try '--help'.", error);
+            process::exit(2);
+        }
+    };
+    let width = args.width;
+    let height = args.height;
 
-    let scene = make_scene();
-    let bounded_scene = bounding_box_tree::build(scene.surfaces).unwrap();
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+
+    let scene = match &args.scene {
+        Some(path) => scene_file::load(path).unwrap_or_else(|error| {
+            eprintln!("error: {}: {}", path.display(), error);
+            process::exit(1);
+        }),
+        None => {
+            let mut rng = SmallRng::seed_from_u64(args.seed);
+            make_scene(&mut rng)
+        }
+    };
+    let build_start = Instant::now();
+    // Each object gets a tree of its own, shared by all of its instances,
+    // with a tree over the instances and other surfaces on top.
+    let objects = bounding_box_tree::build_objects(scene.objects, args.builder);
+    let mut surfaces = scene.surfaces;
+    surfaces.extend(instance::place(&objects, &scene.placements));
+    let bounded_scene = match bounding_box_tree::build(surfaces, args.builder) {
+        Some(bounded_scene) => bounded_scene,
+        None => {
+            eprintln!("error: the scene has no surfaces");
+            process::exit(1);
+        }
+    };
+    eprintln!(
+        "Built bounding box trees for {} objects and {} instances in {:.2?}",
+        objects.len(),
+        scene.placements.len(),
+        build_start.elapsed()
+    );
     let render_options = RenderOptions {
-        screen_width: WIDTH as f64,
-        screen_height: HEIGHT as f64,
-        aspect_ratio: WIDTH as f64 / HEIGHT as f64,
-        samples_per_pixel: 16,
-        max_scatter_depth: 16,
+        screen_width: width as f64,
+        screen_height: height as f64,
+        aspect_ratio: width as f64 / height as f64,
+        samples_per_pixel: args.samples_per_pixel,
+        max_scatter_depth: args.max_scatter_depth,
         camera: scene.camera,
+        area_lights: bounded_scene.lights(),
+        lights: scene.lights,
         scene: bounded_scene,
+        environment: scene.environment,
+        sampling: args.sampling,
+        seed: args.seed,
     };
 
-    let mut pixels_shared = Mutex::new(vec![V3::ZERO; WIDTH * HEIGHT]);
+    let render_start = Instant::now();
+    let mut pixels_shared = Mutex::new(vec![V3::ZERO; width * height]);
 
-    (0..WIDTH * HEIGHT).into_par_iter().for_each(|i| {
-        let screen_x = i % WIDTH;
-        let screen_y = i / WIDTH;
+    (0..width * height).into_par_iter().for_each(|i| {
+        let screen_x = i % width;
+        let screen_y = i / width;
         let color = render::render_pixel(&render_options, screen_x, screen_y);
 
         let mut pixels = pixels_shared.lock().unwrap();
-        pixels[screen_x + screen_y * WIDTH] = color;
+        pixels[screen_x + screen_y * width] = color;
     });
 
+    eprintln!("Rendered in {:.2?}", render_start.elapsed());
+
     let pixels = pixels_shared.get_mut().unwrap();
-    write_png(Path::new("output.png"), WIDTH, HEIGHT, pixels);
+    let result = match args.format {
+        OutputFormat::Png => write_png(&args.output, width, height, pixels),
+        OutputFormat::Exr(pixel_type) => {
+            exr::write(&args.output, width, height, pixels, pixel_type)
+        }
+        OutputFormat::Hdr => hdr::write(&args.output, width, height, pixels),
+    };
+    if let Err(error) = result {
+        eprintln!("error: {}: {}", args.output.display(), error);
+        process::exit(1);
+    }
 }
 
-fn write_png(path: &Path, width: usize, height: usize, pixels: &[V3]) -> () {
+/// Writes 8-bit color, applying a gamma of 2 to the linear radiance.
+fn write_png(path: &Path, width: usize, height: usize, pixels: &[V3]) -> io::Result<()> {
     let bytes: Vec<u8> = pixels
         .iter()
         .flat_map(|color| {
             let V3(components) = color;
             components
                 .iter()
-                .map(|&pixel| ((256.0 * pixel) as i32).clamp(0, 255) as u8)
+                .map(|&pixel| ((256.0 * pixel.sqrt()) as i32).clamp(0, 255) as u8)
         })
         .collect();
-    let file = File::create(path).unwrap();
+    let file = File::create(path)?;
     let writer = BufWriter::new(file);
     let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
     encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
-    encoder
-        .write_header()
-        .unwrap()
-        .write_image_data(&bytes)
-        .unwrap();
+    encoder.write_header()?.write_image_data(&bytes)?;
+    Ok(())
 }
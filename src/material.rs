@@ -1,28 +1,59 @@
-use rand::{thread_rng, Rng};
+use rand::rngs::SmallRng;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
+use crate::microfacet::{fresnel_conductor, scatter_dielectric, Ggx};
 use crate::ray::Ray;
 use crate::ray_hit::RayHit;
-use crate::util::random_unit_vector;
+use crate::texture::Texture;
+use crate::util::{random_unit_vector, Frame};
 use crate::v3::V3;
 
 #[derive(Clone, Copy)]
 pub struct ScatteredRay {
     pub ray: Ray,
     pub attenuation: V3,
+    /// Whether the direction was picked from a distribution `eval` can't
+    /// express, such as a perfect mirror, so lights must be found by the
+    /// scattered ray itself rather than sampled directly.
+    pub is_specular: bool,
 }
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray: Ray, hit: RayHit) -> Option<ScatteredRay>;
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay>;
+
+    fn emitted(&self, _ray: Ray, _hit: RayHit) -> V3 {
+        V3::ZERO
+    }
+
+    /// Whether `emitted` can be nonzero.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Fraction of light arriving from `direction` that is scattered along
+    /// `-ray.direction`, including the cosine at the surface. Only defined
+    /// for materials whose scattered rays aren't specular.
+    fn eval(&self, _ray: Ray, _hit: RayHit, _direction: V3) -> V3 {
+        V3::ZERO
+    }
+
+    /// Density per unit solid angle of `scatter` picking `direction`, which
+    /// relates it to `eval` through `attenuation = eval / pdf`.
+    fn pdf(&self, _ray: Ray, _hit: RayHit, _direction: V3) -> f64 {
+        0.0
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Diffuse {
-    pub color: V3,
+    pub color: Arc<dyn Texture>,
 }
 
 impl Material for Diffuse {
-    fn scatter(&self, _ray: Ray, hit: RayHit) -> Option<ScatteredRay> {
-        let mut direction = hit.normal + random_unit_vector();
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
+        let mut direction = hit.normal + random_unit_vector(rng);
         if direction.is_near_zero() {
             direction = hit.normal
         }
@@ -30,43 +61,267 @@ impl Material for Diffuse {
             ray: Ray {
                 origin: hit.position,
                 direction,
+                time: ray.time,
             },
-            attenuation: self.color,
+            attenuation: self.color.value(hit.uv, hit.position),
+            is_specular: false,
         })
     }
+
+    fn eval(&self, ray: Ray, hit: RayHit, direction: V3) -> V3 {
+        self.color.value(hit.uv, hit.position) * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, _ray: Ray, hit: RayHit, direction: V3) -> f64 {
+        let cos = hit.normal.dot(direction) / direction.length();
+        cos.max(0.0) / PI
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Reflective {
-    pub color: V3,
+    pub color: Arc<dyn Texture>,
     pub fuzz: f64,
 }
 
 impl Material for Reflective {
-    fn scatter(&self, ray: Ray, hit: RayHit) -> Option<ScatteredRay> {
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
         let direction =
-            ray.direction.normalize().reflect(hit.normal) + random_unit_vector() * self.fuzz;
+            ray.direction.normalize().reflect(hit.normal) + random_unit_vector(rng) * self.fuzz;
         if direction.dot(hit.normal) > 0.0 {
             Some(ScatteredRay {
                 ray: Ray {
                     origin: hit.position,
                     direction,
+                    time: ray.time,
                 },
-                attenuation: self.color,
+                attenuation: self.color.value(hit.uv, hit.position),
+                is_specular: self.fuzz == 0.0,
             })
         } else {
             None
         }
     }
+
+    fn eval(&self, ray: Ray, hit: RayHit, direction: V3) -> V3 {
+        self.color.value(hit.uv, hit.position) * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: RayHit, direction: V3) -> f64 {
+        if self.fuzz == 0.0 || direction.dot(hit.normal) <= 0.0 {
+            return 0.0;
+        }
+        // Scattered directions point at uniformly distributed points of a
+        // sphere with radius `fuzz` around the tip of the unit reflection.
+        // The density sums over where `direction` pierces that sphere, each
+        // point's area density projected onto the unit sphere of directions.
+        let reflected = ray.direction.normalize().reflect(hit.normal);
+        let direction = direction.normalize();
+        let along = direction.dot(reflected);
+        let discriminant = along * along - 1.0 + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let root = discriminant.sqrt();
+        let distances_squared: f64 = [along - root, along + root]
+            .iter()
+            .filter(|&&t| t > 0.0)
+            .map(|t| t * t)
+            .sum();
+        distances_squared / (4.0 * PI * self.fuzz * root)
+    }
+}
+
+/// Metal with a complex index of refraction `eta + i k` per color channel,
+/// roughened by GGX microfacets.
+#[derive(Clone, Copy)]
+pub struct Conductor {
+    pub eta: V3,
+    pub k: V3,
+    /// Perceptual roughness along the tangent and bitangent of the shading
+    /// frame; equal values give isotropic highlights.
+    pub roughness: [f64; 2],
+}
+
+#[derive(Clone, Copy)]
+pub enum Metal {
+    Gold,
+    Copper,
+    Aluminium,
+}
+
+impl Conductor {
+    pub fn from_metal(metal: Metal, roughness: [f64; 2]) -> Conductor {
+        let (eta, k) = match metal {
+            Metal::Gold => (V3([0.143, 0.374, 1.442]), V3([3.983, 2.385, 1.603])),
+            Metal::Copper => (V3([0.200, 0.924, 1.102]), V3([3.912, 2.452, 2.142])),
+            Metal::Aluminium => (V3([1.657, 0.880, 0.521]), V3([9.224, 6.270, 4.837])),
+        };
+        Conductor { eta, k, roughness }
+    }
+
+    fn fresnel(&self, cos: f64) -> V3 {
+        let V3(eta) = self.eta;
+        let V3(k) = self.k;
+        V3([0, 1, 2].map(|i| fresnel_conductor(cos, eta[i], k[i])))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let (frame, wo) = local_frame(ray, hit);
+        if wo.0[2] <= 0.0 {
+            return None;
+        }
+        if ggx.is_smooth() {
+            let wi = V3([-wo.0[0], -wo.0[1], wo.0[2]]);
+            return Some(ScatteredRay {
+                ray: Ray {
+                    origin: hit.position,
+                    direction: frame.to_world(wi),
+                    time: ray.time,
+                },
+                attenuation: self.fresnel(wo.0[2]),
+                is_specular: true,
+            });
+        }
+
+        let normal = ggx.sample_visible_normal(wo, [rng.gen(), rng.gen()]);
+        let wi = -wo + normal * (2.0 * wo.dot(normal));
+        if wi.0[2] <= 0.0 {
+            return None;
+        }
+        Some(ScatteredRay {
+            ray: Ray {
+                origin: hit.position,
+                direction: frame.to_world(wi),
+                time: ray.time,
+            },
+            attenuation: self.fresnel(wo.dot(normal)) * (ggx.g(wo, wi) / ggx.g1(wo)),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: RayHit, direction: V3) -> V3 {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let (frame, wo) = local_frame(ray, hit);
+        let wi = frame.to_local(direction.normalize());
+        if wo.0[2] <= 0.0 || wi.0[2] <= 0.0 || ggx.is_smooth() {
+            return V3::ZERO;
+        }
+        let normal = (wo + wi).normalize();
+        // The cosine of the incoming direction cancels out.
+        self.fresnel(wo.dot(normal)) * (ggx.d(normal) * ggx.g(wo, wi) / (4.0 * wo.0[2]))
+    }
+
+    fn pdf(&self, ray: Ray, hit: RayHit, direction: V3) -> f64 {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let (frame, wo) = local_frame(ray, hit);
+        let wi = frame.to_local(direction.normalize());
+        if wo.0[2] <= 0.0 || wi.0[2] <= 0.0 || ggx.is_smooth() {
+            return 0.0;
+        }
+        let normal = (wo + wi).normalize();
+        // Reflecting about the microfacet normal compresses solid angle by
+        // `4 (wo · normal)`.
+        ggx.visible_normal_pdf(wo, normal) / (4.0 * wo.dot(normal))
+    }
+}
+
+/// Glass or another clear dielectric with a rough surface that both reflects
+/// and transmits through GGX microfacets, like frosted glass.
+#[derive(Clone, Copy)]
+pub struct Dielectric {
+    /// Index of refraction inside the surface relative to outside.
+    pub ratio: f64,
+    /// Perceptual roughness along the tangent and bitangent of the shading
+    /// frame; zero gives smooth glass.
+    pub roughness: [f64; 2],
+    /// Fraction of light absorbed per unit distance travelled inside, per
+    /// color channel.
+    pub absorption: V3,
+}
+
+impl Dielectric {
+    /// Relative index of refraction from the side `hit` is on to the other.
+    fn eta(&self, hit: RayHit) -> f64 {
+        if hit.on_front_face {
+            self.ratio
+        } else {
+            1.0 / self.ratio
+        }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let (frame, wo) = local_frame(ray, hit);
+        if wo.0[2] <= 0.0 {
+            return None;
+        }
+        let eta = self.eta(hit);
+        let is_smooth = ggx.is_smooth();
+        let normal = if is_smooth {
+            V3([0.0, 0.0, 1.0])
+        } else {
+            ggx.sample_visible_normal(wo, [rng.gen(), rng.gen()])
+        };
+
+        let wi = scatter_dielectric(wo, normal, eta, rng.gen())?;
+        // The Fresnel term cancels with the chance of picking reflection or
+        // transmission. Like `Refractive`, transmission leaves radiance
+        // unscaled by the squared ratio, which cancels out for closed shapes.
+        let attenuation = if is_smooth {
+            1.0
+        } else {
+            ggx.g(wo, wi) / ggx.g1(wo)
+        };
+        Some(ScatteredRay {
+            ray: Ray {
+                origin: hit.position,
+                direction: frame.to_world(wi),
+                time: ray.time,
+            },
+            attenuation: transmittance(self.absorption, ray, hit) * attenuation,
+            is_specular: is_smooth,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: RayHit, direction: V3) -> V3 {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let (frame, wo) = local_frame(ray, hit);
+        let wi = frame.to_local(direction.normalize());
+        if wo.0[2] <= 0.0 || wi.0[2] == 0.0 || ggx.is_smooth() {
+            return V3::ZERO;
+        }
+        let value = ggx.dielectric_eval(wo, wi, self.eta(hit));
+        transmittance(self.absorption, ray, hit) * value
+    }
+
+    fn pdf(&self, ray: Ray, hit: RayHit, direction: V3) -> f64 {
+        let ggx = Ggx::from_roughness(self.roughness);
+        let (frame, wo) = local_frame(ray, hit);
+        let wi = frame.to_local(direction.normalize());
+        if wo.0[2] <= 0.0 || wi.0[2] == 0.0 || ggx.is_smooth() {
+            return 0.0;
+        }
+        ggx.dielectric_pdf(wo, wi, self.eta(hit))
+    }
 }
 
+/// Smooth glass or another clear dielectric.
 #[derive(Clone, Copy)]
 pub struct Refractive {
     pub ratio: f64,
+    /// Fraction of light absorbed per unit distance travelled inside, per
+    /// color channel.
+    pub absorption: V3,
 }
 
 impl Material for Refractive {
-    fn scatter(&self, ray: Ray, hit: RayHit) -> Option<ScatteredRay> {
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
         let adjusted_ratio = if hit.on_front_face {
             1.0 / self.ratio
         } else {
@@ -78,7 +333,7 @@ impl Material for Refractive {
         let is_refracting = {
             let sin_angle = (1.0 - cos_angle * cos_angle).sqrt();
             adjusted_ratio * sin_angle <= 1.0
-                || Self::reflectance(cos_angle, adjusted_ratio) > thread_rng().gen()
+                && Self::reflectance(cos_angle, adjusted_ratio) <= rng.gen()
         };
 
         let direction = if is_refracting {
@@ -92,8 +347,10 @@ impl Material for Refractive {
             ray: Ray {
                 origin: hit.position,
                 direction,
+                time: ray.time,
             },
-            attenuation: V3([1.0, 1.0, 1.0]),
+            attenuation: transmittance(self.absorption, ray, hit),
+            is_specular: true,
         })
     }
 }
@@ -109,3 +366,45 @@ impl Refractive {
         r + (1.0 - r) * y
     }
 }
+
+/// Emits `color` from the front face of a surface and absorbs all incoming light.
+#[derive(Clone, Copy)]
+pub struct DiffuseLight {
+    pub color: V3,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray: Ray, _hit: RayHit, _rng: &mut SmallRng) -> Option<ScatteredRay> {
+        None
+    }
+
+    fn emitted(&self, _ray: Ray, hit: RayHit) -> V3 {
+        if hit.on_front_face {
+            self.color
+        } else {
+            V3::ZERO
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// The frame around the hit normal, lined up with the surface tangent, and
+/// the outgoing direction in it.
+fn local_frame(ray: Ray, hit: RayHit) -> (Frame, V3) {
+    let frame = Frame::from_normal_and_tangent(hit.normal, hit.tangent);
+    let wo = frame.to_local(-ray.direction.normalize());
+    (frame, wo)
+}
+
+/// Fraction of light left after Beer–Lambert absorption along `ray` up to
+/// `hit`, which only happens inside the medium, where rays hit back faces.
+fn transmittance(absorption: V3, ray: Ray, hit: RayHit) -> V3 {
+    if hit.on_front_face || absorption.0 == [0.0; 3] {
+        return V3([1.0, 1.0, 1.0]);
+    }
+    let distance = hit.t * ray.direction.length();
+    absorption.map(|a| (-a * distance).exp())
+}
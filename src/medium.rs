@@ -0,0 +1,361 @@
+//! Participating media such as fog and smoke, which scatter light at random
+//! points inside a volume rather than on a surface.
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+use crate::bounding_box::BoundingBox;
+use crate::grid::Grid;
+use crate::m4::{Transform, M4};
+use crate::material::{Material, ScatteredRay};
+use crate::ray::Ray;
+use crate::ray_hit::RayHit;
+use crate::surface::{RayHitMaterial, Surface};
+use crate::texture::Texture;
+use crate::util::{random_unit_vector, Frame};
+use crate::v3::V3;
+
+/// Volume of uniform density inside a closed `boundary`, where rays travel
+/// an exponentially distributed distance before scattering by
+/// `phase_function`.
+pub struct ConstantMedium {
+    pub boundary: Box<dyn Surface>,
+    /// Chance of scattering per unit distance travelled.
+    pub density: f64,
+    pub phase_function: Arc<dyn Material>,
+}
+
+impl Surface for ConstantMedium {
+    /// Always a miss, since where rays scatter is random and decided by
+    /// `sample_hit`.
+    fn hit(&self, _ray: Ray, _t_min: f64, _t_max: f64) -> Option<RayHitMaterial<'_>> {
+        None
+    }
+
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        let (entry, exit) = boundary_span(&*self.boundary, ray, t_min, t_max)?;
+        let ray_length = ray.direction.length();
+        let distance_inside = (exit - entry) * ray_length;
+        let distance = -(1.0 - rng.gen::<f64>()).ln() / self.density;
+        if distance > distance_inside {
+            return None;
+        }
+        let t = entry + distance / ray_length;
+        Some(RayHitMaterial {
+            hit: medium_hit(ray, t),
+            material: &*self.phase_function,
+            surface: self,
+        })
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        self.boundary.calculate_bounding_box()
+    }
+
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, _rng: &mut SmallRng) -> f64 {
+        match boundary_span(&*self.boundary, ray, t_min, t_max) {
+            Some((entry, exit)) => (-self.density * (exit - entry) * ray.direction.length()).exp(),
+            None => 1.0,
+        }
+    }
+}
+
+/// Where `ray` is inside `boundary` within `t_min..t_max`, as the distances
+/// along the ray where that part starts and ends.
+fn boundary_span(boundary: &dyn Surface, ray: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+    const EXIT_OFFSET: f64 = 0.0001;
+    let entry = boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?.hit.t;
+    let exit = boundary.hit(ray, entry + EXIT_OFFSET, f64::INFINITY)?.hit.t;
+    let entry = entry.max(t_min).max(0.0);
+    let exit = exit.min(t_max);
+    if entry < exit {
+        Some((entry, exit))
+    } else {
+        None
+    }
+}
+
+/// Scattering event inside a medium, which has no surface to take a normal
+/// or texture coordinates from.
+fn medium_hit(ray: Ray, t: f64) -> RayHit {
+    RayHit {
+        position: ray.at(t),
+        normal: V3([1.0, 0.0, 0.0]),
+        t,
+        on_front_face: true,
+        barycentric: None,
+        uv: [0.0, 0.0],
+        tangent: V3::ZERO,
+    }
+}
+
+pub struct GridMediumOptions {
+    /// Absorption per unit distance over the medium, if it absorbs at all.
+    pub absorption: Option<Grid>,
+    /// Scattering per unit distance over the medium, if it scatters at all.
+    pub scattering: Option<Grid>,
+    /// Radiance emitted where the medium absorbs, as a multiple of
+    /// `emission_color`.
+    pub emission: Option<Grid>,
+    pub emission_color: V3,
+    /// Factor applied to both `absorption` and `scattering`.
+    pub density: f64,
+    pub phase_function: Arc<dyn Material>,
+    /// Places the unit cube the grids fill in the world.
+    pub transform: Transform,
+}
+
+/// Volume whose absorption, scattering and emission vary over voxel grids.
+///
+/// Rays are traced through it by delta tracking: they take exponentially
+/// distributed steps as if the whole volume were as dense as its densest
+/// voxel, and at each step either get absorbed, scatter or carry on, with
+/// chances in proportion to the actual density there. Shadow rays take the
+/// same steps but are dimmed at each by the chance of hitting something
+/// instead (ratio tracking), which is less noisy than blocking them.
+pub struct GridMedium {
+    absorption: Option<Grid>,
+    scattering: Option<Grid>,
+    density: f64,
+    /// Largest extinction anywhere in the medium.
+    majorant: f64,
+    phase_function: Arc<dyn Material>,
+    absorbed: Absorbed,
+    transform: Transform,
+}
+
+impl GridMedium {
+    pub fn new(options: GridMediumOptions) -> GridMedium {
+        let max = |grid: &Option<Grid>| grid.as_ref().map_or(0.0, Grid::max);
+        GridMedium {
+            majorant: options.density * (max(&options.absorption) + max(&options.scattering)),
+            absorption: options.absorption,
+            scattering: options.scattering,
+            density: options.density,
+            phase_function: options.phase_function,
+            absorbed: Absorbed {
+                emission: options.emission,
+                color: options.emission_color,
+                inverse: options.transform.inverse,
+            },
+            transform: options.transform,
+        }
+    }
+
+    /// Absorption and scattering per unit distance at `point` of the unit
+    /// cube.
+    fn coefficients(&self, point: V3) -> (f64, f64) {
+        let value = |grid: &Option<Grid>| grid.as_ref().map_or(0.0, |grid| grid.value(point));
+        (
+            self.density * value(&self.absorption),
+            self.density * value(&self.scattering),
+        )
+    }
+
+    /// `ray` in the space of the unit cube, with the same distances along
+    /// it, and the part of it within `t_min..t_max` inside the cube.
+    fn grid_span(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<(Ray, f64, f64)> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let grid_ray = Ray {
+            origin: self.transform.inverse.transform_point(ray.origin),
+            direction: self.transform.inverse.transform_vector(ray.direction),
+            time: ray.time,
+        };
+        let (mut entry, mut exit) = (t_min, t_max);
+        for i in 0..3 {
+            let direction_inv = 1.0 / grid_ray.direction.0[i];
+            let t0 = -grid_ray.origin.0[i] * direction_inv;
+            let t1 = (1.0 - grid_ray.origin.0[i]) * direction_inv;
+            entry = entry.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+        }
+        if entry < exit {
+            Some((grid_ray, entry, exit))
+        } else {
+            None
+        }
+    }
+}
+
+impl Surface for GridMedium {
+    /// Always a miss, since where rays scatter or get absorbed is random and
+    /// decided by `sample_hit`.
+    fn hit(&self, _ray: Ray, _t_min: f64, _t_max: f64) -> Option<RayHitMaterial<'_>> {
+        None
+    }
+
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        let (grid_ray, entry, exit) = self.grid_span(ray, t_min, t_max)?;
+        let mean_step = 1.0 / (self.majorant * ray.direction.length());
+        let mut t = entry;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() * mean_step;
+            if t >= exit {
+                return None;
+            }
+            let (absorption, scattering) = self.coefficients(grid_ray.at(t));
+            let event = rng.gen::<f64>() * self.majorant;
+            let material: &dyn Material = if event < absorption {
+                &self.absorbed
+            } else if event < absorption + scattering {
+                &*self.phase_function
+            } else {
+                continue;
+            };
+            return Some(RayHitMaterial {
+                hit: medium_hit(ray, t),
+                material,
+                surface: self,
+            });
+        }
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        self.transform.bounding_box(BoundingBox {
+            minimum: V3::ZERO,
+            maximum: V3([1.0, 1.0, 1.0]),
+        })
+    }
+
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, rng: &mut SmallRng) -> f64 {
+        let (grid_ray, entry, exit) = match self.grid_span(ray, t_min, t_max) {
+            Some(span) => span,
+            None => return 1.0,
+        };
+        let mean_step = 1.0 / (self.majorant * ray.direction.length());
+        let mut transmittance = 1.0;
+        let mut t = entry;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() * mean_step;
+            if t >= exit {
+                return transmittance;
+            }
+            let (absorption, scattering) = self.coefficients(grid_ray.at(t));
+            transmittance *= 1.0 - (absorption + scattering) / self.majorant;
+        }
+    }
+}
+
+/// What a ray absorbed by a `GridMedium` ends at, seeing the light the
+/// medium emits there.
+struct Absorbed {
+    emission: Option<Grid>,
+    color: V3,
+    /// Takes world positions into the unit cube of `emission`.
+    inverse: M4,
+}
+
+impl Material for Absorbed {
+    fn scatter(&self, _ray: Ray, _hit: RayHit, _rng: &mut SmallRng) -> Option<ScatteredRay> {
+        None
+    }
+
+    fn emitted(&self, _ray: Ray, hit: RayHit) -> V3 {
+        match &self.emission {
+            Some(emission) => {
+                self.color * emission.value(self.inverse.transform_point(hit.position))
+            }
+            None => V3::ZERO,
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.emission.is_some()
+    }
+}
+
+/// Scatters light equally in all directions.
+#[derive(Clone)]
+pub struct Isotropic {
+    pub color: Arc<dyn Texture>,
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
+        Some(ScatteredRay {
+            ray: Ray {
+                origin: hit.position,
+                direction: random_unit_vector(rng),
+                time: ray.time,
+            },
+            attenuation: self.color.value(hit.uv, hit.position),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: RayHit, direction: V3) -> V3 {
+        self.color.value(hit.uv, hit.position) * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, _ray: Ray, _hit: RayHit, _direction: V3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Henyey–Greenstein phase function, scattering mostly forwards for `g`
+/// towards 1 and mostly backwards for `g` towards -1.
+#[derive(Clone)]
+pub struct HenyeyGreenstein {
+    pub color: Arc<dyn Texture>,
+    /// Mean cosine of the scattering angle, in `(-1, 1)`.
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    /// Density per solid angle of turning by an angle with cosine `cos`.
+    fn phase(&self, cos: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
+        let g = self.g;
+        let u: f64 = rng.gen();
+        let cos = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let t = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - t * t) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let frame = Frame::from_normal(ray.direction.normalize());
+        Some(ScatteredRay {
+            ray: Ray {
+                origin: hit.position,
+                direction: frame.to_world(V3([sin * phi.cos(), sin * phi.sin(), cos])),
+                time: ray.time,
+            },
+            attenuation: self.color.value(hit.uv, hit.position),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: RayHit, direction: V3) -> V3 {
+        self.color.value(hit.uv, hit.position) * self.pdf(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, _hit: RayHit, direction: V3) -> f64 {
+        self.phase(ray.direction.normalize().dot(direction.normalize()))
+    }
+}
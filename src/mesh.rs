@@ -0,0 +1,125 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+
+use crate::bounding_box::BoundingBox;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::surface::{
+    area_pdf, intersect_triangle, random_triangle_point, triangle_area, triangle_hit,
+    RayHitMaterial, Surface, PDF_T_MIN,
+};
+use crate::v3::V3;
+
+/// Triangle mesh storing each vertex attribute once, with faces referring to
+/// them by index.
+pub struct Mesh {
+    pub positions: Vec<V3>,
+    pub normals: Vec<V3>,
+    pub uvs: Vec<[f64; 2]>,
+    pub faces: Vec<Face>,
+    pub groups: Vec<Group>,
+}
+
+#[derive(Clone, Copy)]
+pub struct Face {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+/// Named range of consecutive faces, e.g. an OBJ `g` or `o` statement.
+pub struct Group {
+    pub name: String,
+    pub faces: Range<usize>,
+}
+
+struct SharedMesh {
+    mesh: Mesh,
+    material: Arc<dyn Material>,
+}
+
+struct MeshTriangle {
+    shared: Arc<SharedMesh>,
+    face: usize,
+}
+
+impl Mesh {
+    /// Splits the mesh into one surface per face, all sharing the vertex
+    /// buffers and `material`.
+    pub fn into_surfaces(self, material: Arc<dyn Material>) -> Vec<Box<dyn Surface>> {
+        let face_count = self.faces.len();
+        let shared = Arc::new(SharedMesh {
+            mesh: self,
+            material,
+        });
+        (0..face_count)
+            .map(|face| {
+                Box::new(MeshTriangle {
+                    shared: shared.clone(),
+                    face,
+                }) as Box<dyn Surface>
+            })
+            .collect()
+    }
+
+    pub fn face_vertices(&self, face: usize) -> [V3; 3] {
+        let [a, b, c] = self.faces[face].positions;
+        [self.positions[a], self.positions[b], self.positions[c]]
+    }
+
+    pub fn face_normals(&self, face: usize) -> Option<[V3; 3]> {
+        self.faces[face]
+            .normals
+            .map(|[a, b, c]| [self.normals[a], self.normals[b], self.normals[c]])
+    }
+
+    pub fn face_uvs(&self, face: usize) -> Option<[[f64; 2]; 3]> {
+        self.faces[face]
+            .uvs
+            .map(|[a, b, c]| [self.uvs[a], self.uvs[b], self.uvs[c]])
+    }
+}
+
+impl Surface for MeshTriangle {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        let mesh = &self.shared.mesh;
+        let vertices = mesh.face_vertices(self.face);
+        let (t, barycentric) = intersect_triangle(ray, vertices, t_min, t_max)?;
+        Some(RayHitMaterial {
+            hit: triangle_hit(
+                ray,
+                t,
+                barycentric,
+                vertices,
+                mesh.face_normals(self.face),
+                mesh.face_uvs(self.face),
+            ),
+            material: &(*self.shared.material),
+            surface: self,
+        })
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_points(&self.shared.mesh.face_vertices(self.face))
+    }
+
+    fn is_light(&self) -> bool {
+        self.shared.material.is_emissive()
+    }
+
+    fn pdf_value(&self, origin: V3, direction: V3) -> f64 {
+        let ray = Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+        let area = triangle_area(self.shared.mesh.face_vertices(self.face));
+        area_pdf(ray, self.hit(ray, PDF_T_MIN, f64::INFINITY), area)
+    }
+
+    fn random_direction(&self, origin: V3, rng: &mut SmallRng) -> V3 {
+        random_triangle_point(self.shared.mesh.face_vertices(self.face), rng) - origin
+    }
+}
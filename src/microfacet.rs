@@ -0,0 +1,228 @@
+//! Trowbridge–Reitz (GGX) microfacet distribution, with directions given in
+//! a local frame where the macro surface normal is the z axis.
+
+use std::f64::consts::PI;
+
+use crate::v3::V3;
+
+/// Distribution of microfacet normals with roughness `alpha_x` along the
+/// tangent and `alpha_y` along the bitangent.
+#[derive(Clone, Copy)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+/// Smallest alpha along either direction, which keeps the distribution
+/// finite when only one direction is rough. It is below the threshold of
+/// `is_smooth`, so surfaces without any roughness still count as mirrors.
+const MIN_ALPHA: f64 = 1e-4;
+
+impl Ggx {
+    /// Maps perceptual roughness in `[0, 1]` along the two tangent
+    /// directions to the distribution's `alpha = roughness²`.
+    pub fn from_roughness([u, v]: [f64; 2]) -> Ggx {
+        Ggx {
+            alpha_x: (u * u).max(MIN_ALPHA),
+            alpha_y: (v * v).max(MIN_ALPHA),
+        }
+    }
+
+    /// Whether the surface is so smooth it is better treated as a mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    /// Density of microfacets facing `normal`, per unit area of the surface
+    /// and solid angle.
+    pub fn d(&self, normal: V3) -> f64 {
+        let V3([x, y, z]) = normal;
+        if z <= 0.0 {
+            return 0.0;
+        }
+        let t = (x / self.alpha_x).powi(2) + (y / self.alpha_y).powi(2) + z * z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    /// Smith's auxiliary function, the ratio of masked to visible microfacet
+    /// area seen from `w`.
+    pub fn lambda(&self, w: V3) -> f64 {
+        let V3([x, y, z]) = w;
+        if z == 0.0 {
+            return f64::INFINITY;
+        }
+        let alpha_tan_squared = ((x * self.alpha_x).powi(2) + (y * self.alpha_y).powi(2)) / (z * z);
+        0.5 * (-1.0 + (1.0 + alpha_tan_squared).sqrt())
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: V3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated fraction of microfacets visible from both `wo` and
+    /// `wi`.
+    pub fn g(&self, wo: V3, wi: V3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Picks a microfacet normal among those visible from `wo`, weighted by
+    /// their projected area, after Heitz, "Sampling the GGX Distribution of
+    /// Visible Normals" (2018).
+    pub fn sample_visible_normal(&self, wo: V3, [u1, u2]: [f64; 2]) -> V3 {
+        let V3([x, y, z]) = wo;
+        // Stretch to the configuration where the roughness is 1.
+        let view = V3([self.alpha_x * x, self.alpha_y * y, z]).normalize();
+        let length_squared = view.0[0] * view.0[0] + view.0[1] * view.0[1];
+        let t1 = if length_squared > 0.0 {
+            V3([-view.0[1], view.0[0], 0.0]) * (1.0 / length_squared.sqrt())
+        } else {
+            V3([1.0, 0.0, 0.0])
+        };
+        let t2 = view.cross(t1);
+
+        // Uniformly on the disk, warped to the visible half of it.
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + view.0[2]);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let normal = t1 * p1 + t2 * p2 + view * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        let V3([nx, ny, nz]) = normal;
+        V3([self.alpha_x * nx, self.alpha_y * ny, nz.max(1e-6)]).normalize()
+    }
+
+    /// Density of `sample_visible_normal` picking `normal`.
+    pub fn visible_normal_pdf(&self, wo: V3, normal: V3) -> f64 {
+        self.g1(wo) * wo.dot(normal).max(0.0) * self.d(normal) / wo.0[2]
+    }
+
+    /// Fraction of light arriving from `wi` that a rough dielectric boundary
+    /// with relative index of refraction `eta` reflects or transmits along
+    /// `wo`, times the cosine of `wi`.
+    pub fn dielectric_eval(&self, wo: V3, wi: V3, eta: f64) -> f64 {
+        let normal = match dielectric_normal(wo, wi, eta) {
+            Some(normal) => normal,
+            None => return 0.0,
+        };
+        let fresnel = fresnel_dielectric(wo.dot(normal), eta);
+        let d_g = self.d(normal) * self.g(wo, wi);
+        // The cosine of the incoming direction cancels out.
+        if wi.0[2] > 0.0 {
+            fresnel * d_g / (4.0 * wo.0[2])
+        } else {
+            let denominator = (wi.dot(normal) + wo.dot(normal) / eta).powi(2);
+            (1.0 - fresnel) * d_g * (wi.dot(normal) * wo.dot(normal)).abs()
+                / (wo.0[2] * denominator)
+        }
+    }
+
+    /// Density of `scatter_dielectric` about a visible normal picking `wi`.
+    pub fn dielectric_pdf(&self, wo: V3, wi: V3, eta: f64) -> f64 {
+        let normal = match dielectric_normal(wo, wi, eta) {
+            Some(normal) => normal,
+            None => return 0.0,
+        };
+        let fresnel = fresnel_dielectric(wo.dot(normal), eta);
+        let normal_pdf = self.visible_normal_pdf(wo, normal);
+        if wi.0[2] > 0.0 {
+            // Reflecting about the microfacet normal compresses solid angle
+            // by `4 (wo · normal)`.
+            fresnel * normal_pdf / (4.0 * wo.dot(normal))
+        } else {
+            // Change of variables from the microfacet normal to the refracted
+            // direction.
+            let denominator = (wi.dot(normal) + wo.dot(normal) / eta).powi(2);
+            (1.0 - fresnel) * normal_pdf * wi.dot(normal).abs() / denominator
+        }
+    }
+}
+
+/// Reflects or refracts `wo` at a microfacet with `normal` on a dielectric
+/// boundary with relative index of refraction `eta`, reflecting when `u` in
+/// `[0, 1)` falls below the Fresnel term. Fails if the result ends up on the
+/// wrong side of the macro surface.
+pub fn scatter_dielectric(wo: V3, normal: V3, eta: f64, u: f64) -> Option<V3> {
+    let cos = wo.dot(normal);
+    let reflected = -wo + normal * (2.0 * cos);
+    let wi = if fresnel_dielectric(cos, eta) > u {
+        reflected
+    } else {
+        refract(wo, normal, eta).unwrap_or(reflected)
+    };
+    let is_reflected = wi.0[2] > 0.0;
+    if is_reflected != (wi.dot(normal) > 0.0) || wi.0[2] == 0.0 {
+        return None;
+    }
+    Some(wi)
+}
+
+/// The microfacet normal that scatters `wo` to `wi` through a dielectric
+/// boundary, if one facing both of them from the right sides exists.
+fn dielectric_normal(wo: V3, wi: V3, eta: f64) -> Option<V3> {
+    let normal = if wi.0[2] > 0.0 {
+        wo + wi
+    } else {
+        wo + wi * eta
+    };
+    if normal.length_squared() == 0.0 {
+        return None;
+    }
+    let normal = normal.normalize();
+    let normal = if normal.0[2] < 0.0 { -normal } else { normal };
+    let is_same_side = wi.dot(normal) * wi.0[2] > 0.0;
+    if wo.dot(normal) <= 0.0 || !is_same_side {
+        return None;
+    }
+    Some(normal)
+}
+
+/// Fraction of light reflected by a conductor with complex index of
+/// refraction `eta + i k`, at an angle with cosine `cos` to the normal.
+pub fn fresnel_conductor(cos: f64, eta: f64, k: f64) -> f64 {
+    let cos_squared = cos * cos;
+    let sin_squared = 1.0 - cos_squared;
+    let eta_squared = eta * eta;
+    let k_squared = k * k;
+
+    let t0 = eta_squared - k_squared - sin_squared;
+    let a_squared_plus_b_squared = (t0 * t0 + 4.0 * eta_squared * k_squared).sqrt();
+    let t1 = a_squared_plus_b_squared + cos_squared;
+    let a = (0.5 * (a_squared_plus_b_squared + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos_squared * a_squared_plus_b_squared + sin_squared * sin_squared;
+    let t4 = t2 * sin_squared;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+/// Fraction of unpolarized light reflected at the boundary into a dielectric
+/// with relative index of refraction `eta`, at an angle with cosine `cos` to
+/// the normal on the side the light arrives from. Beyond the critical angle
+/// everything is reflected.
+pub fn fresnel_dielectric(cos: f64, eta: f64) -> f64 {
+    let sin_squared_transmitted = (1.0 - cos * cos) / (eta * eta);
+    if sin_squared_transmitted >= 1.0 {
+        return 1.0;
+    }
+    let cos_transmitted = (1.0 - sin_squared_transmitted).sqrt();
+    let rs = (cos - eta * cos_transmitted) / (cos + eta * cos_transmitted);
+    let rp = (eta * cos - cos_transmitted) / (eta * cos + cos_transmitted);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Direction `w` refracts to through a boundary with normal `normal` on its
+/// side and relative index of refraction `eta`, if it isn't totally
+/// internally reflected.
+pub fn refract(w: V3, normal: V3, eta: f64) -> Option<V3> {
+    let cos = w.dot(normal);
+    let sin_squared_transmitted = (1.0 - cos * cos).max(0.0) / (eta * eta);
+    if sin_squared_transmitted >= 1.0 {
+        return None;
+    }
+    let cos_transmitted = (1.0 - sin_squared_transmitted).sqrt();
+    Some(-w * (1.0 / eta) + normal * (cos / eta - cos_transmitted))
+}
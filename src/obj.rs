@@ -0,0 +1,183 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::mesh::{Face, Group, Mesh};
+use crate::v3::V3;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(error) => write!(f, "{}", error),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(error: io::Error) -> ObjError {
+        ObjError::Io(error)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Mesh, ObjError> {
+    let file = File::open(path)?;
+    parse(BufReader::new(file))
+}
+
+/// Parses `v`, `vn`, `vt`, `f`, `g` and `o` statements of a Wavefront OBJ
+/// file, triangulating polygons as fans. Other statements are ignored.
+pub fn parse<R: BufRead>(reader: R) -> Result<Mesh, ObjError> {
+    let mut mesh = Mesh {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        faces: Vec::new(),
+        groups: Vec::new(),
+    };
+    let mut group_name = String::from("default");
+    let mut group_start = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = index + 1;
+        let error = |message: String| ObjError::Parse {
+            line: line_number,
+            message,
+        };
+
+        let content = line.split('#').next().unwrap_or("");
+        let mut tokens = content.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => mesh.positions.push(parse_v3(&arguments).map_err(error)?),
+            "vn" => mesh.normals.push(parse_v3(&arguments).map_err(error)?),
+            "vt" => {
+                let coordinates = parse_floats(&arguments, 1, 3).map_err(error)?;
+                mesh.uvs
+                    .push([coordinates[0], coordinates.get(1).copied().unwrap_or(0.0)]);
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, got {}",
+                        arguments.len()
+                    )));
+                }
+                let vertices = arguments
+                    .iter()
+                    .map(|vertex| parse_face_vertex(vertex, &mesh))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                for i in 1..vertices.len() - 1 {
+                    let [a, b, c] = [vertices[0], vertices[i], vertices[i + 1]];
+                    mesh.faces.push(Face {
+                        positions: [a.0, b.0, c.0],
+                        uvs: a.1.zip(b.1).zip(c.1).map(|((a, b), c)| [a, b, c]),
+                        normals: a.2.zip(b.2).zip(c.2).map(|((a, b), c)| [a, b, c]),
+                    });
+                }
+            }
+            "g" | "o" => {
+                push_group(&mut mesh, group_name, group_start);
+                group_name = arguments.join(" ");
+                group_start = mesh.faces.len();
+            }
+            _ => (),
+        }
+    }
+    push_group(&mut mesh, group_name, group_start);
+
+    Ok(mesh)
+}
+
+fn push_group(mesh: &mut Mesh, name: String, start: usize) {
+    let end = mesh.faces.len();
+    if end > start {
+        mesh.groups.push(Group {
+            name,
+            faces: start..end,
+        });
+    }
+}
+
+fn parse_v3(arguments: &[&str]) -> Result<V3, String> {
+    let coordinates = parse_floats(arguments, 3, 4)?;
+    Ok(V3([coordinates[0], coordinates[1], coordinates[2]]))
+}
+
+fn parse_floats(arguments: &[&str], min: usize, max: usize) -> Result<Vec<f64>, String> {
+    if arguments.len() < min || arguments.len() > max {
+        return Err(format!(
+            "expected {} to {} numbers, got {}",
+            min,
+            max,
+            arguments.len()
+        ));
+    }
+    arguments
+        .iter()
+        .map(|argument| match argument.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(number),
+            _ => Err(format!("invalid number '{}'", argument)),
+        })
+        .collect()
+}
+
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+/// Parses `p`, `p/t`, `p//n` or `p/t/n` into zero-based indices.
+fn parse_face_vertex(vertex: &str, mesh: &Mesh) -> Result<FaceVertex, String> {
+    let mut parts = vertex.split('/');
+    let position = match parts.next() {
+        Some(index) => resolve_index(index, mesh.positions.len(), "vertex")?,
+        None => return Err(format!("invalid face vertex '{}'", vertex)),
+    };
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, mesh.uvs.len(), "texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(resolve_index(index, mesh.normals.len(), "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex '{}'", vertex));
+    }
+    Ok((position, uv, normal))
+}
+
+/// Resolves a one-based OBJ index, where negative values count back from the
+/// most recently defined element.
+fn resolve_index(index: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid {} index '{}'", kind, index))?;
+    let resolved = if value < 0 {
+        count as i64 + value
+    } else {
+        value - 1
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        Err(format!(
+            "{} index {} out of range (have {})",
+            kind, value, count
+        ))
+    } else {
+        Ok(resolved as usize)
+    }
+}
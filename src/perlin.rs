@@ -0,0 +1,85 @@
+use rand::rngs::SmallRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::util::random_unit_vector;
+use crate::v3::V3;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise over lattice points hashed through per-axis permutation
+/// tables, so the same seed always gives the same pattern.
+pub struct Perlin {
+    gradients: Vec<V3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| random_unit_vector(&mut rng))
+            .collect();
+        let mut permutation = || {
+            let mut indices: Vec<usize> = (0..POINT_COUNT).collect();
+            indices.shuffle(&mut rng);
+            indices
+        };
+        let permutations = [permutation(), permutation(), permutation()];
+        Perlin {
+            gradients,
+            permutations,
+        }
+    }
+
+    /// Noise in about `[-1, 1]`, interpolating trilinearly between the
+    /// lattice gradients with Hermite smoothing to hide the lattice.
+    pub fn noise(&self, position: V3) -> f64 {
+        let V3(p) = position;
+        let cell = p.map(f64::floor);
+        let fraction = [p[0] - cell[0], p[1] - cell[1], p[2] - cell[2]];
+        let [i, j, k] = cell.map(|x| x as i64);
+        let [u, v, w] = fraction.map(|t| t * t * (3.0 - 2.0 * t));
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradient(i + di, j + dj, k + dk);
+                    let weight = V3([
+                        fraction[0] - di as f64,
+                        fraction[1] - dj as f64,
+                        fraction[2] - dk as f64,
+                    ]);
+                    let (di, dj, dk) = (di as f64, dj as f64, dk as f64);
+                    sum += (di * u + (1.0 - di) * (1.0 - u))
+                        * (dj * v + (1.0 - dj) * (1.0 - v))
+                        * (dk * w + (1.0 - dk) * (1.0 - w))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        sum
+    }
+
+    /// Magnitude of the noise summed over `octaves` octaves, each at double
+    /// the frequency and half the weight of the previous one.
+    pub fn turbulence(&self, position: V3, octaves: usize) -> f64 {
+        let mut sum = 0.0;
+        let mut position = position;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            sum += weight * self.noise(position);
+            weight *= 0.5;
+            position = position * 2.0;
+        }
+        sum.abs()
+    }
+
+    fn gradient(&self, i: i64, j: i64, k: i64) -> V3 {
+        let [x, y, z] = &self.permutations;
+        let mask = POINT_COUNT as i64 - 1;
+        let index = x[(i & mask) as usize] ^ y[(j & mask) as usize] ^ z[(k & mask) as usize];
+        self.gradients[index]
+    }
+}
@@ -0,0 +1,293 @@
+//! Uber material after Burley, "Physically-Based Shading at Disney" (2012),
+//! with the transmission of the 2015 follow-up.
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+
+use crate::material::{Material, ScatteredRay};
+use crate::microfacet::{scatter_dielectric, Ggx};
+use crate::ray::Ray;
+use crate::ray_hit::RayHit;
+use crate::texture::Texture;
+use crate::util::{luminance, Frame};
+use crate::v3::V3;
+
+/// Roughness below which the specular lobes would be too sharp to evaluate
+/// reliably.
+const MIN_ROUGHNESS: f64 = 0.05;
+/// Index of refraction below which transmitted rays would barely bend, which
+/// leaves no half vector to evaluate the transmission lobe with.
+const MIN_IOR: f64 = 1.01;
+
+/// Blend of a diffuse base with sheen, a specular layer, a clearcoat and
+/// rough transmission, driven by artist-friendly parameters in `[0, 1]`.
+///
+/// Scalar parameters take the mean of their texture's channels, so they can
+/// be constants or grayscale textures.
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    /// Blends from a dielectric to a metal tinted by the base color.
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    /// Strength of the dielectric specular reflection, where 0.5 is that of
+    /// an index of refraction of 1.5.
+    pub specular: Arc<dyn Texture>,
+    /// Tints the dielectric specular reflection towards the base color.
+    pub specular_tint: Arc<dyn Texture>,
+    /// Extra reflection at grazing angles, for cloth.
+    pub sheen: Arc<dyn Texture>,
+    /// Strength of a second, colorless specular layer on top.
+    pub clearcoat: Arc<dyn Texture>,
+    /// Sharpness of the clearcoat highlight.
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    /// Blends from an opaque dielectric to glass tinted by the base color.
+    pub transmission: Arc<dyn Texture>,
+    /// Index of refraction of the transmission, at least `MIN_IOR`.
+    pub ior: f64,
+}
+
+impl Principled {
+    /// The parameters at a hit, in the frame around its normal.
+    fn lobes(&self, hit: RayHit) -> Lobes {
+        let scalar = |texture: &Arc<dyn Texture>| {
+            let V3([r, g, b]) = texture.value(hit.uv, hit.position);
+            ((r + g + b) / 3.0).clamp(0.0, 1.0)
+        };
+        let base_color = self.base_color.value(hit.uv, hit.position);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness).max(MIN_ROUGHNESS);
+        let specular = scalar(&self.specular);
+        let transmission = scalar(&self.transmission);
+        let clearcoat = scalar(&self.clearcoat);
+
+        let base_luminance = luminance(base_color);
+        let tint = if base_luminance > 0.0 {
+            base_color * (1.0 / base_luminance)
+        } else {
+            V3([1.0, 1.0, 1.0])
+        };
+        let white = V3([1.0, 1.0, 1.0]);
+        let specular_tint = scalar(&self.specular_tint);
+        let dielectric_color =
+            (white * (1.0 - specular_tint) + tint * specular_tint) * 0.08 * specular;
+        let specular_color = dielectric_color * (1.0 - metallic) + base_color * metallic;
+        let ior = self.ior.max(MIN_IOR);
+
+        let weights = [
+            (1.0 - metallic) * (1.0 - transmission),
+            1.0 - (1.0 - metallic) * transmission,
+            0.25 * clearcoat,
+            (1.0 - metallic) * transmission,
+        ];
+        let total: f64 = weights.iter().sum();
+        let alpha = 0.1 + (0.001 - 0.1) * scalar(&self.clearcoat_gloss);
+        Lobes {
+            base_color,
+            roughness,
+            sheen: scalar(&self.sheen),
+            specular_color,
+            eta: if hit.on_front_face { ior } else { 1.0 / ior },
+            ggx: Ggx::from_roughness([roughness, roughness]),
+            clearcoat_alpha: alpha,
+            weights,
+            probabilities: weights.map(|weight| weight / total),
+            frame: Frame::from_normal(hit.normal),
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray: Ray, hit: RayHit, rng: &mut SmallRng) -> Option<ScatteredRay> {
+        let lobes = self.lobes(hit);
+        let wo = lobes.frame.to_local(-ray.direction.normalize());
+        if wo.0[2] <= 0.0 {
+            return None;
+        }
+
+        let mut choice: f64 = rng.gen();
+        let lobe = lobes
+            .probabilities
+            .iter()
+            .position(|&probability| {
+                choice -= probability;
+                choice < 0.0
+            })
+            .unwrap_or(TRANSMISSION);
+        let wi = match lobe {
+            DIFFUSE => {
+                let r: f64 = rng.gen::<f64>().sqrt();
+                let phi = 2.0 * PI * rng.gen::<f64>();
+                V3([r * phi.cos(), r * phi.sin(), (1.0 - r * r).max(0.0).sqrt()])
+            }
+            SPECULAR => {
+                let normal = lobes.ggx.sample_visible_normal(wo, [rng.gen(), rng.gen()]);
+                reflect(wo, normal)
+            }
+            CLEARCOAT => {
+                let alpha_squared = lobes.clearcoat_alpha * lobes.clearcoat_alpha;
+                let u: f64 = rng.gen();
+                let cos = ((1.0 - alpha_squared.powf(1.0 - u)) / (1.0 - alpha_squared)).sqrt();
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * PI * rng.gen::<f64>();
+                reflect(wo, V3([sin * phi.cos(), sin * phi.sin(), cos]))
+            }
+            _ => {
+                let normal = lobes.ggx.sample_visible_normal(wo, [rng.gen(), rng.gen()]);
+                scatter_dielectric(wo, normal, lobes.eta, rng.gen())?
+            }
+        };
+
+        // Weighting by the density of all lobes together rather than the one
+        // picked keeps the variance down where lobes overlap.
+        let pdf = lobes.pdf(wo, wi);
+        if pdf.is_nan() || pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatteredRay {
+            ray: Ray {
+                origin: hit.position,
+                direction: lobes.frame.to_world(wi),
+                time: ray.time,
+            },
+            attenuation: lobes.eval(wo, wi) * (1.0 / pdf),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: RayHit, direction: V3) -> V3 {
+        let lobes = self.lobes(hit);
+        let wo = lobes.frame.to_local(-ray.direction.normalize());
+        let wi = lobes.frame.to_local(direction.normalize());
+        if wo.0[2] <= 0.0 {
+            return V3::ZERO;
+        }
+        lobes.eval(wo, wi)
+    }
+
+    fn pdf(&self, ray: Ray, hit: RayHit, direction: V3) -> f64 {
+        let lobes = self.lobes(hit);
+        let wo = lobes.frame.to_local(-ray.direction.normalize());
+        let wi = lobes.frame.to_local(direction.normalize());
+        if wo.0[2] <= 0.0 {
+            return 0.0;
+        }
+        lobes.pdf(wo, wi)
+    }
+}
+
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const TRANSMISSION: usize = 3;
+
+/// Parameters of `Principled` resolved at one hit.
+struct Lobes {
+    base_color: V3,
+    roughness: f64,
+    sheen: f64,
+    /// Reflectance of the specular lobe at normal incidence.
+    specular_color: V3,
+    /// Relative index of refraction into the far side of the surface.
+    eta: f64,
+    ggx: Ggx,
+    clearcoat_alpha: f64,
+    /// How much each lobe contributes.
+    weights: [f64; 4],
+    /// Chance of sampling each lobe.
+    probabilities: [f64; 4],
+    frame: Frame,
+}
+
+impl Lobes {
+    /// Sum of the lobes scattering light from `wi` to `wo`, times the cosine
+    /// of `wi`, with both directions in the local frame.
+    fn eval(&self, wo: V3, wi: V3) -> V3 {
+        let mut value = V3::ZERO;
+        if self.weights[TRANSMISSION] > 0.0 && wi.0[2] != 0.0 {
+            let transmission =
+                self.ggx.dielectric_eval(wo, wi, self.eta) * self.weights[TRANSMISSION];
+            // Only light passing through the surface picks up its color.
+            value = value
+                + if wi.0[2] < 0.0 {
+                    self.base_color * transmission
+                } else {
+                    V3([transmission; 3])
+                };
+        }
+        if wi.0[2] <= 0.0 {
+            return value;
+        }
+
+        let half = (wo + wi).normalize();
+        let cos_half = wi.dot(half);
+        if self.weights[DIFFUSE] > 0.0 {
+            // Burley's diffuse with retroreflection at grazing angles.
+            let grazing = 0.5 + 2.0 * self.roughness * cos_half * cos_half;
+            let retroreflection = (1.0 + (grazing - 1.0) * schlick_weight(wi.0[2]))
+                * (1.0 + (grazing - 1.0) * schlick_weight(wo.0[2]));
+            let sheen = self.sheen * schlick_weight(cos_half);
+            let diffuse = self.base_color * (retroreflection / PI) + V3([sheen; 3]);
+            value = value + diffuse * (self.weights[DIFFUSE] * wi.0[2]);
+        }
+        if self.weights[SPECULAR] > 0.0 {
+            let fresnel = self.specular_color
+                + (V3([1.0, 1.0, 1.0]) - self.specular_color) * schlick_weight(cos_half);
+            let d_g = self.ggx.d(half) * self.ggx.g(wo, wi);
+            value = value + fresnel * (self.weights[SPECULAR] * d_g / (4.0 * wo.0[2]));
+        }
+        if self.weights[CLEARCOAT] > 0.0 {
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_half);
+            let d = gtr1(half.0[2], self.clearcoat_alpha);
+            let g = Ggx::from_roughness([0.5, 0.5]).g(wo, wi);
+            value = value + V3([self.weights[CLEARCOAT] * fresnel * d * g / (4.0 * wo.0[2]); 3]);
+        }
+        value
+    }
+
+    /// Density of sampling `wi` from `wo` with the lobes picked by their
+    /// probabilities.
+    fn pdf(&self, wo: V3, wi: V3) -> f64 {
+        let [diffuse, specular, clearcoat, transmission] = self.probabilities;
+        let mut pdf = 0.0;
+        if transmission > 0.0 && wi.0[2] != 0.0 {
+            pdf += transmission * self.ggx.dielectric_pdf(wo, wi, self.eta);
+        }
+        if wi.0[2] <= 0.0 {
+            return pdf;
+        }
+        let half = (wo + wi).normalize();
+        let cos_out = wo.dot(half);
+        if cos_out <= 0.0 {
+            return pdf;
+        }
+        pdf += diffuse * wi.0[2] / PI;
+        pdf += specular * self.ggx.visible_normal_pdf(wo, half) / (4.0 * cos_out);
+        pdf += clearcoat * gtr1(half.0[2], self.clearcoat_alpha) * half.0[2] / (4.0 * cos_out);
+        pdf
+    }
+}
+
+fn reflect(wo: V3, normal: V3) -> V3 {
+    -wo + normal * (2.0 * wo.dot(normal))
+}
+
+/// Schlick's approximation of how the Fresnel term grows towards grazing
+/// angles, `(1 - cos)⁵`.
+fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos).clamp(0.0, 1.0).powi(5)
+}
+
+/// Generalized Trowbridge–Reitz distribution with exponent 1, whose long
+/// tail gives the clearcoat its haze.
+fn gtr1(cos: f64, alpha: f64) -> f64 {
+    if cos <= 0.0 {
+        return 0.0;
+    }
+    let alpha_squared = alpha * alpha;
+    let t = 1.0 + (alpha_squared - 1.0) * cos * cos;
+    (alpha_squared - 1.0) / (PI * alpha_squared.ln() * t)
+}
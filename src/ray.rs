@@ -4,6 +4,8 @@ use crate::v3::V3;
 pub struct Ray {
     pub origin: V3,
     pub direction: V3,
+    /// Moment within the camera's shutter interval the ray was cast at.
+    pub time: f64,
 }
 
 impl Ray {
@@ -6,4 +6,14 @@ pub struct RayHit {
     pub normal: V3,
     pub t: f64,
     pub on_front_face: bool,
+    /// Barycentric coordinates `(u, v)` of the hit for triangles, weighting
+    /// the second and third vertex respectively.
+    pub barycentric: Option<[f64; 2]>,
+    /// Texture coordinates of the hit.
+    pub uv: [f64; 2],
+    /// Direction in which `uv[0]` increases along the surface, which orients
+    /// anisotropic materials. Neither normalized nor necessarily
+    /// perpendicular to `normal`, and zero where the surface has no such
+    /// direction.
+    pub tangent: V3,
 }
@@ -1,14 +1,31 @@
-use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 
+use crate::bounding_box_tree::BoundingBoxTree;
 use crate::camera::Camera;
+use crate::environment::Environment;
+use crate::light::Light;
 use crate::ray::Ray;
-use crate::surface::Surface;
+use crate::surface::{RayHitMaterial, Surface};
+use crate::util::mix_seed;
 use crate::v3::V3;
 
 type Color = V3;
-const SKY_TOP: Color = V3([1.0, 1.0, 1.0]);
-const SKY_BOTTOM: Color = V3([0.5, 0.7, 1.0]);
 const T_MIN: f64 = 0.00001;
+/// Fraction of the distance to a light that shadow rays stop short of, so
+/// that surfaces right at the light don't block it.
+const SHADOW_EPSILON: f64 = 0.000001;
+
+/// How light sources are found after a non-specular scattering.
+#[derive(Clone, Copy)]
+pub enum Sampling {
+    /// Only by the scattered ray hitting them.
+    Material,
+    /// Only by sampling them with shadow rays.
+    Lights,
+    /// By both, weighted with the power heuristic.
+    MultipleImportance,
+}
 
 pub struct RenderOptions {
     pub screen_width: f64,
@@ -17,11 +34,23 @@ pub struct RenderOptions {
     pub samples_per_pixel: i32,
     pub max_scatter_depth: i32,
     pub camera: Camera,
-    pub scene: Box<dyn Surface>,
+    pub scene: BoundingBoxTree,
+    /// Indices of the surfaces in `scene` that are sampled as lights.
+    pub area_lights: Vec<usize>,
+    /// Point, spot and directional lights, which are sampled whatever the
+    /// `sampling`.
+    pub lights: Vec<Box<dyn Light>>,
+    pub environment: Box<dyn Environment>,
+    pub sampling: Sampling,
+    /// Seed of all random sampling; each pixel draws from its own stream
+    /// derived from it, so renders don't depend on thread scheduling.
+    pub seed: u64,
 }
 
+/// Averages the linear radiance arriving through the pixel over all samples.
 pub fn render_pixel(opts: &RenderOptions, pixel_x: usize, pixel_y: usize) -> Color {
-    let mut rng = rand::thread_rng();
+    let pixel_index = pixel_y as u64 * opts.screen_width as u64 + pixel_x as u64;
+    let mut rng = SmallRng::seed_from_u64(mix_seed(opts.seed, pixel_index));
     let mut color = V3::ZERO;
     for _ in 0..opts.samples_per_pixel {
         let sample_x = pixel_x as f64 + rng.gen::<f64>();
@@ -30,30 +59,188 @@ pub fn render_pixel(opts: &RenderOptions, pixel_x: usize, pixel_y: usize) -> Col
         let normalized_x = (2.0 * sample_x / opts.screen_width - 1.0) * opts.aspect_ratio;
         let normalized_y = -(2.0 * sample_y / opts.screen_height - 1.0);
 
-        let ray = opts.camera.ray_from(normalized_x, normalized_y);
-        color = color + ray_color(ray, &(*opts.scene), opts.max_scatter_depth);
+        let ray = opts.camera.ray_from(normalized_x, normalized_y, &mut rng);
+        color = color + ray_color(opts, ray, opts.max_scatter_depth, &mut rng);
+    }
+    color * (1.0 / opts.samples_per_pixel as f64)
+}
+
+fn ray_color(opts: &RenderOptions, mut ray: Ray, depth: i32, rng: &mut SmallRng) -> Color {
+    let mut color = V3::ZERO;
+    let mut throughput = V3([1.0, 1.0, 1.0]);
+    // Density of the material picking the current ray, unless it was
+    // specular or the camera's, in which case lights can't be sampled.
+    let mut scatter_pdf = None;
+    for _ in 0..depth {
+        let result = match opts.scene.sample_hit(ray, T_MIN, f64::INFINITY, rng) {
+            Some(result) => result,
+            None => {
+                let weight = match scatter_pdf {
+                    Some(pdf) if opts.environment.is_light() => {
+                        let light_pdf = opts.environment.pdf_value(ray.direction)
+                            / sampled_light_count(opts) as f64;
+                        emission_weight(opts, pdf, light_pdf)
+                    }
+                    _ => 1.0,
+                };
+                let radiance = match scatter_pdf {
+                    Some(_) => opts.environment.radiance(ray.direction),
+                    None => opts.environment.visible_radiance(ray.direction),
+                };
+                color = color + throughput * radiance * weight;
+                break;
+            }
+        };
+        let weight = match scatter_pdf {
+            Some(pdf) if result.surface.is_light() => {
+                let light_pdf = result.surface.pdf_value(ray.origin, ray.direction)
+                    / sampled_light_count(opts) as f64;
+                emission_weight(opts, pdf, light_pdf)
+            }
+            _ => 1.0,
+        };
+        if weight > 0.0 {
+            color = color + throughput * result.material.emitted(ray, result.hit) * weight;
+        }
+
+        let scattered_ray = match result.material.scatter(ray, result.hit, rng) {
+            Some(scattered_ray) => scattered_ray,
+            None => break,
+        };
+        scatter_pdf = if scattered_ray.is_specular {
+            None
+        } else {
+            if !matches!(opts.sampling, Sampling::Material) {
+                color = color + throughput * sample_light(opts, ray, result, rng);
+            }
+            color = color + throughput * sample_lights(opts, ray, result, rng);
+            Some(
+                result
+                    .material
+                    .pdf(ray, result.hit, scattered_ray.ray.direction),
+            )
+        };
+        throughput = throughput * scattered_ray.attenuation;
+        ray = scattered_ray.ray;
+    }
+    color
+}
+
+/// Number of lights picked from by `sample_light`: the area lights and the
+/// environment if it is worth sampling.
+fn sampled_light_count(opts: &RenderOptions) -> usize {
+    opts.area_lights.len() + opts.environment.is_light() as usize
+}
+
+/// Weight of light found by a scattered ray drawn with density `pdf`, which
+/// `sample_light` could have found with density `light_pdf`.
+fn emission_weight(opts: &RenderOptions, pdf: f64, light_pdf: f64) -> f64 {
+    match opts.sampling {
+        Sampling::Material => 1.0,
+        Sampling::Lights => 0.0,
+        Sampling::MultipleImportance => power_heuristic(pdf, light_pdf),
+    }
+}
+
+/// Estimates the light reaching `ray` from a randomly picked area light or
+/// the environment via a single shadow ray from the hit towards it, dimmed
+/// by any media on the way.
+fn sample_light(
+    opts: &RenderOptions,
+    ray: Ray,
+    result: RayHitMaterial<'_>,
+    rng: &mut SmallRng,
+) -> Color {
+    let count = sampled_light_count(opts);
+    if count == 0 {
+        return V3::ZERO;
+    }
+    let origin = result.hit.position;
+    // Indices past the area lights stand for the environment.
+    let light = opts
+        .area_lights
+        .get(rng.gen_range(0..count))
+        .map(|&index| opts.scene.surface(index));
+    let (direction, pdf) = match light {
+        Some(light) => {
+            let direction = light.random_direction(origin, rng);
+            (direction, light.pdf_value(origin, direction))
+        }
+        None => {
+            let direction = opts.environment.random_direction(rng);
+            (direction, opts.environment.pdf_value(direction))
+        }
+    };
+    let pdf = pdf / count as f64;
+    if pdf <= 0.0 {
+        return V3::ZERO;
+    }
+    let scattering = result.material.eval(ray, result.hit, direction);
+    if scattering.0 == [0.0; 3] {
+        return V3::ZERO;
+    }
+    let weight = match opts.sampling {
+        Sampling::MultipleImportance => {
+            power_heuristic(pdf, result.material.pdf(ray, result.hit, direction))
+        }
+        _ => 1.0,
     };
-    color.map(|x| (x / (opts.samples_per_pixel as f64)).sqrt())
-}
-
-fn ray_color(ray: Ray, scene: &dyn Surface, depth: i32) -> Color {
-    if depth > 0 {
-        match scene.hit(ray, T_MIN, f64::INFINITY) {
-            Some(result) => match result.material.scatter(ray, result.hit) {
-                Some(scattered_ray) => {
-                    scattered_ray.attenuation * ray_color(scattered_ray.ray, scene, depth - 1)
-                }
-                None => V3::ZERO,
-            },
-            None => sky_color(ray),
+
+    let shadow_ray = Ray {
+        origin,
+        direction,
+        time: ray.time,
+    };
+    let (emitted, t_max) = match light {
+        Some(light) => match light.hit(shadow_ray, T_MIN, f64::INFINITY) {
+            Some(light_result) => (
+                light_result.material.emitted(shadow_ray, light_result.hit),
+                light_result.hit.t * (1.0 - SHADOW_EPSILON),
+            ),
+            None => return V3::ZERO,
+        },
+        None => (opts.environment.radiance(direction), f64::INFINITY),
+    };
+    let transmittance = opts.scene.transmittance(shadow_ray, T_MIN, t_max, rng);
+    scattering * emitted * (transmittance * weight / pdf)
+}
+
+/// Sums the light reaching `ray` from every point, spot and directional
+/// light through whatever lies between it and the hit.
+fn sample_lights(
+    opts: &RenderOptions,
+    ray: Ray,
+    result: RayHitMaterial<'_>,
+    rng: &mut SmallRng,
+) -> Color {
+    let position = result.hit.position;
+    let mut color = V3::ZERO;
+    for light in &opts.lights {
+        let sample = match light.sample(position) {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let scattering = result.material.eval(ray, result.hit, sample.direction);
+        if scattering.0 == [0.0; 3] {
+            continue;
+        }
+        let shadow_ray = Ray {
+            origin: position,
+            direction: sample.direction,
+            time: ray.time,
+        };
+        let t_max = sample.distance * (1.0 - SHADOW_EPSILON);
+        let transmittance = opts.scene.transmittance(shadow_ray, T_MIN, t_max, rng);
+        if transmittance > 0.0 {
+            color = color + scattering * sample.irradiance * transmittance;
         }
-    } else {
-        V3::ZERO
     }
+    color
 }
 
-fn sky_color(ray: Ray) -> Color {
-    let unit_direction = ray.direction.normalize();
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    SKY_TOP * (1.0 - t) + SKY_BOTTOM * t
+/// Weight of a sample drawn with density `pdf` when another strategy could
+/// have drawn it with density `other_pdf`.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf_squared = pdf * pdf;
+    pdf_squared / (pdf_squared + other_pdf * other_pdf)
 }
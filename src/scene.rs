@@ -1,26 +1,38 @@
 use std::f64::consts::PI;
+use std::sync::Arc;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::camera::{Camera, CameraOptions};
+use crate::environment::{Environment, Gradient};
+use crate::instance::Placement;
+use crate::light::Light;
 use crate::material::{Diffuse, Material, Reflective, Refractive};
-use crate::surface::{Sphere, Surface};
+use crate::surface::{Quad, Sphere, Surface};
 use crate::v3::V3;
 
 pub struct Scene {
     pub camera: Camera,
     pub surfaces: Vec<Box<dyn Surface>>,
+    /// Geometry that only appears through `placements`, kept apart so that
+    /// each object gets a bounding box tree of its own that all of its
+    /// instances share.
+    pub objects: Vec<Vec<Box<dyn Surface>>>,
+    pub placements: Vec<Placement>,
+    pub lights: Vec<Box<dyn Light>>,
+    /// Light arriving from where rays escape the scene.
+    pub environment: Box<dyn Environment>,
 }
 
-pub fn make_scene() -> Scene {
+/// Randomly scattered small spheres around three large ones, laid out by `rng`.
+pub fn make_scene<R: Rng>(rng: &mut R) -> Scene {
     let mut surfaces: Vec<Box<dyn Surface>> = Vec::new();
-    let mut rng = thread_rng();
 
     surfaces.push(Box::new(Sphere {
         center: V3([0.0, -1000.0, 0.0]),
         radius: 1000.0,
-        material: Box::new(Diffuse {
-            color: V3([0.5, 0.5, 0.5]),
+        material: Arc::new(Diffuse {
+            color: Arc::new(V3([0.5, 0.5, 0.5])),
         }),
     }));
 
@@ -34,10 +46,12 @@ pub fn make_scene() -> Scene {
             ]);
 
             if (center - V3([4.0, 0.2, 0.0])).length() > 0.9 {
-                let material: Box<dyn Material> = if choose_mat < 0.8 {
+                let material: Arc<dyn Material> = if choose_mat < 0.8 {
                     let c1 = V3([rng.gen(), rng.gen(), rng.gen()]);
                     let c2 = V3([rng.gen(), rng.gen(), rng.gen()]);
-                    Box::new(Diffuse { color: c1 * c2 })
+                    Arc::new(Diffuse {
+                        color: Arc::new(c1 * c2),
+                    })
                 } else if choose_mat < 0.95 {
                     let color = V3([
                         rng.gen_range(0.5..1.0),
@@ -45,9 +59,15 @@ pub fn make_scene() -> Scene {
                         rng.gen_range(0.5..1.0),
                     ]);
                     let fuzz = rng.gen_range(0.0..0.5);
-                    Box::new(Reflective { color, fuzz })
+                    Arc::new(Reflective {
+                        color: Arc::new(color),
+                        fuzz,
+                    })
                 } else {
-                    Box::new(Refractive { ratio: 1.5 })
+                    Arc::new(Refractive {
+                        ratio: 1.5,
+                        absorption: V3::ZERO,
+                    })
                 };
                 surfaces.push(Box::new(Sphere {
                     center,
@@ -61,20 +81,23 @@ pub fn make_scene() -> Scene {
     surfaces.push(Box::new(Sphere {
         center: V3([0.0, 1.0, 0.0]),
         radius: 1.0,
-        material: Box::new(Refractive { ratio: 1.5 }),
+        material: Arc::new(Refractive {
+            ratio: 1.5,
+            absorption: V3::ZERO,
+        }),
     }));
     surfaces.push(Box::new(Sphere {
         center: V3([-4.0, 1.0, 0.0]),
         radius: 1.0,
-        material: Box::new(Diffuse {
-            color: V3([0.4, 0.2, 0.1]),
+        material: Arc::new(Diffuse {
+            color: Arc::new(V3([0.4, 0.2, 0.1])),
         }),
     }));
     surfaces.push(Box::new(Sphere {
         center: V3([4.0, 1.0, 0.0]),
         radius: 1.0,
-        material: Box::new(Reflective {
-            color: V3([0.7, 0.6, 0.5]),
+        material: Arc::new(Reflective {
+            color: Arc::new(V3([0.7, 0.6, 0.5])),
             fuzz: 0.0,
         }),
     }));
@@ -85,7 +108,48 @@ pub fn make_scene() -> Scene {
         vertical_field_of_view: PI / 6.0,
         aperture: 0.2,
         focus_distance: Some(10.0),
+        shutter_open: 0.0,
+        shutter_close: 0.0,
     });
 
-    Scene { camera, surfaces }
+    Scene {
+        camera,
+        surfaces,
+        objects: Vec::new(),
+        placements: Vec::new(),
+        lights: Vec::new(),
+        environment: Box::new(Gradient::default()),
+    }
+}
+
+/// Pushes the six faces of the axis-aligned box from `minimum` to `maximum`,
+/// facing outwards.
+pub fn push_box(
+    surfaces: &mut Vec<Box<dyn Surface>>,
+    minimum: V3,
+    maximum: V3,
+    material: Arc<dyn Material>,
+) {
+    let V3([x0, y0, z0]) = minimum;
+    let V3([x1, y1, z1]) = maximum;
+    let dx = V3([x1 - x0, 0.0, 0.0]);
+    let dy = V3([0.0, y1 - y0, 0.0]);
+    let dz = V3([0.0, 0.0, z1 - z0]);
+
+    let faces = [
+        (V3([x0, y0, z1]), dx, dy),
+        (V3([x1, y0, z1]), -dz, dy),
+        (V3([x1, y0, z0]), -dx, dy),
+        (V3([x0, y0, z0]), dz, dy),
+        (V3([x0, y1, z1]), dx, -dz),
+        (V3([x0, y0, z0]), dx, dz),
+    ];
+    for (corner, u, v) in faces {
+        surfaces.push(Box::new(Quad {
+            corner,
+            u,
+            v,
+            material: material.clone(),
+        }));
+    }
 }
@@ -0,0 +1,933 @@
+//! Text format for describing scenes, one statement per line:
+//!
+//! ```text
+//! # Comments run to the end of the line.
+//! camera look_from=278,278,-800 look_at=278,278,0 vertical_field_of_view=40
+//! environment none
+//! material white diffuse color=0.73,0.73,0.73
+//! material lamp diffuse_light color=15,15,15
+//! texture tiles image path=tiles.png wrap=repeat
+//! texture floor checker even=0.9,0.9,0.9 odd=tiles scale=50
+//! material floor diffuse color=floor
+//! quad corner=343,554,332 u=-130,0,0 v=0,0,-105 material=lamp
+//! sphere center=190,90,190 radius=90 material=white
+//! object bunny path=bunny.obj material=white
+//! instance bunny scale=500 rotate=0,30,0 translate=370,0,350
+//! moving_sphere start_center=100,90,100 end_center=100,140,100 radius=40 material=white
+//! material smoke isotropic color=0.8,0.8,0.8
+//! medium box minimum=0,0,0 maximum=165,330,165 density=0.01 material=smoke rotate=0,15,0 translate=265,0,295
+//! medium grid minimum=100,0,200 maximum=300,300,400 scattering=smoke.grid absorption=smoke.grid emission=fire.grid emission_color=8,3,1 material=smoke
+//! point_light position=278,500,278 intensity=50000,50000,50000
+//! ```
+//!
+//! Each statement starts with a keyword, optionally followed by a name, and
+//! then `field=value` pairs. Vectors are written as three comma-separated
+//! numbers, angles in degrees, and paths relative to the scene file. Colors
+//! of textures and surface materials take either a vector or the name of a
+//! texture. Voxel grids are read in the format described in [`crate::grid`].
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::camera::{Camera, CameraOptions};
+use crate::environment::{Constant, Environment, EnvironmentMap, Gradient};
+use crate::grid::{self, Grid};
+use crate::hdr;
+use crate::instance::{Instance, Placement};
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::m4::{Motion, Pose, Transform};
+use crate::material::{
+    Conductor, Dielectric, Diffuse, DiffuseLight, Material, Metal, Reflective, Refractive,
+};
+use crate::medium::{ConstantMedium, GridMedium, GridMediumOptions, HenyeyGreenstein, Isotropic};
+use crate::obj;
+use crate::perlin::Perlin;
+use crate::principled::Principled;
+use crate::scene::{push_box, Scene};
+use crate::sky::PreethamSky;
+use crate::surface::{MovingSphere, Quad, Sphere, Surface, Triangle};
+use crate::texture::{Checker, ImageTexture, Marble, Noise, Texture, Turbulence, Wood, WrapMode};
+use crate::v3::V3;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse {
+        line: usize,
+        field: Option<String>,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(error) => write!(f, "{}", error),
+            SceneError::Parse {
+                line,
+                field: Some(field),
+                message,
+            } => write!(f, "line {}, field '{}': {}", line, field, message),
+            SceneError::Parse {
+                line,
+                field: None,
+                message,
+            } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(error: io::Error) -> SceneError {
+        SceneError::Io(error)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path)?;
+    let base_directory = path.parent().unwrap_or_else(|| Path::new(""));
+    parse(&source, base_directory)
+}
+
+/// Parses a scene, resolving relative paths against `base_directory`.
+pub fn parse(source: &str, base_directory: &Path) -> Result<Scene, SceneError> {
+    let mut parser = Parser {
+        base_directory,
+        camera: None,
+        environment: Box::new(Gradient::default()),
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        object_names: HashMap::new(),
+        objects: Vec::new(),
+        placements: Vec::new(),
+        surfaces: Vec::new(),
+        lights: Vec::new(),
+        sun: None,
+    };
+    for (index, line) in source.lines().enumerate() {
+        parser.parse_line(index + 1, line)?;
+    }
+
+    let camera = parser.camera.ok_or(SceneError::Parse {
+        line: source.lines().count().max(1),
+        field: None,
+        message: String::from("missing camera statement"),
+    })?;
+    let mut lights = parser.lights;
+    if let Some(sun) = parser.sun {
+        lights.push(Box::new(sun));
+    }
+    Ok(Scene {
+        camera,
+        surfaces: parser.surfaces,
+        objects: parser.objects,
+        placements: parser.placements,
+        lights,
+        environment: parser.environment,
+    })
+}
+
+struct Parser<'a> {
+    base_directory: &'a Path,
+    camera: Option<Camera>,
+    environment: Box<dyn Environment>,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    /// Indices into `objects` by name.
+    object_names: HashMap<String, usize>,
+    objects: Vec<Vec<Box<dyn Surface>>>,
+    placements: Vec<Placement>,
+    surfaces: Vec<Box<dyn Surface>>,
+    lights: Vec<Box<dyn Light>>,
+    /// Sun of a `sky` environment, lit as a directional light.
+    sun: Option<DirectionalLight>,
+}
+
+impl Parser<'_> {
+    fn parse_line(&mut self, line: usize, text: &str) -> Result<(), SceneError> {
+        let content = text.split('#').next().unwrap_or("");
+        let mut tokens = content.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => return Ok(()),
+        };
+        let mut positional = Vec::new();
+        let mut fields = Fields {
+            line,
+            values: Vec::new(),
+        };
+        for token in tokens {
+            match token.split_once('=') {
+                Some((key, value)) => {
+                    if fields.values.iter().any(|(other, _, _)| *other == key) {
+                        return Err(fields.error(key, "given more than once"));
+                    }
+                    fields.values.push((key, value, false))
+                }
+                None if fields.values.is_empty() => positional.push(token),
+                None => {
+                    return Err(error(
+                        line,
+                        format!("expected field=value, got '{}'", token),
+                    ))
+                }
+            }
+        }
+
+        match keyword {
+            "camera" => {
+                expect_positional(line, &positional, &[])?;
+                let options = CameraOptions {
+                    look_from: fields.v3("look_from")?,
+                    look_at: fields.v3("look_at")?,
+                    vertical_field_of_view: fields.f64("vertical_field_of_view")?.to_radians(),
+                    aperture: fields.f64_or("aperture", 0.0)?,
+                    focus_distance: fields.optional_f64("focus_distance")?,
+                    shutter_open: fields.f64_or("shutter_open", 0.0)?,
+                    shutter_close: fields.f64_or("shutter_close", 0.0)?,
+                };
+                fields.finish()?;
+                self.camera = Some(Camera::new(options));
+            }
+            "environment" => {
+                let [kind] = expect_positional(line, &positional, &["kind"])?;
+                self.environment = self.parse_environment(line, kind, &mut fields)?;
+                fields.finish()?;
+            }
+            "texture" => {
+                let [name, kind] = expect_positional(line, &positional, &["name", "kind"])?;
+                if self.textures.contains_key(name) {
+                    return Err(error(line, format!("texture '{}' already defined", name)));
+                }
+                let texture = self.parse_texture(line, kind, &mut fields)?;
+                fields.finish()?;
+                self.textures.insert(name.to_string(), texture);
+            }
+            "material" => {
+                let [name, kind] = expect_positional(line, &positional, &["name", "kind"])?;
+                if self.materials.contains_key(name) {
+                    return Err(error(line, format!("material '{}' already defined", name)));
+                }
+                let material = self.parse_material(line, kind, &mut fields)?;
+                fields.finish()?;
+                self.materials.insert(name.to_string(), material);
+            }
+            "sphere" => {
+                expect_positional(line, &positional, &[])?;
+                let sphere = Sphere {
+                    center: fields.v3("center")?,
+                    radius: fields.f64("radius")?,
+                    material: self.material(&mut fields)?,
+                };
+                fields.finish()?;
+                self.surfaces.push(Box::new(sphere));
+            }
+            "moving_sphere" => {
+                expect_positional(line, &positional, &[])?;
+                let sphere = MovingSphere {
+                    start_center: fields.v3("start_center")?,
+                    end_center: fields.v3("end_center")?,
+                    start_time: fields.f64_or("start_time", 0.0)?,
+                    end_time: fields.f64_or("end_time", 1.0)?,
+                    radius: fields.f64("radius")?,
+                    material: self.material(&mut fields)?,
+                };
+                fields.finish()?;
+                self.surfaces.push(Box::new(sphere));
+            }
+            "quad" => {
+                expect_positional(line, &positional, &[])?;
+                let quad = Quad {
+                    corner: fields.v3("corner")?,
+                    u: fields.v3("u")?,
+                    v: fields.v3("v")?,
+                    material: self.material(&mut fields)?,
+                };
+                fields.finish()?;
+                self.surfaces.push(Box::new(quad));
+            }
+            "triangle" => {
+                expect_positional(line, &positional, &[])?;
+                let vertices = [fields.v3("a")?, fields.v3("b")?, fields.v3("c")?];
+                let normals = match fields.optional_v3("normal_a")? {
+                    Some(normal_a) => {
+                        Some([normal_a, fields.v3("normal_b")?, fields.v3("normal_c")?])
+                    }
+                    None => None,
+                };
+                let triangle = Triangle {
+                    vertices,
+                    normals,
+                    material: self.material(&mut fields)?,
+                };
+                fields.finish()?;
+                self.surfaces.push(Box::new(triangle));
+            }
+            "box" => {
+                expect_positional(line, &positional, &[])?;
+                let minimum = fields.v3("minimum")?;
+                let maximum = fields.v3("maximum")?;
+                let material = self.material(&mut fields)?;
+                fields.finish()?;
+                push_box(&mut self.surfaces, minimum, maximum, material);
+            }
+            "mesh" => {
+                expect_positional(line, &positional, &[])?;
+                let path = self.path(&mut fields, "path")?;
+                let material = self.material(&mut fields)?;
+                fields.finish()?;
+                let mesh = obj::load(&path)
+                    .map_err(|e| fields.error("path", format!("{}: {}", path.display(), e)))?;
+                self.surfaces.extend(mesh.into_surfaces(material));
+            }
+            "object" => {
+                let [name] = expect_positional(line, &positional, &["name"])?;
+                if self.object_names.contains_key(name) {
+                    return Err(error(line, format!("object '{}' already defined", name)));
+                }
+                let path = self.path(&mut fields, "path")?;
+                let material = self.material(&mut fields)?;
+                fields.finish()?;
+                let mesh = obj::load(&path)
+                    .map_err(|e| fields.error("path", format!("{}: {}", path.display(), e)))?;
+                if mesh.faces.is_empty() {
+                    return Err(fields.error("path", format!("{}: no faces", path.display())));
+                }
+                self.object_names
+                    .insert(name.to_string(), self.objects.len());
+                self.objects.push(mesh.into_surfaces(material));
+            }
+            "instance" => {
+                let [name] = expect_positional(line, &positional, &["object"])?;
+                let object = *self
+                    .object_names
+                    .get(name)
+                    .ok_or_else(|| error(line, format!("unknown object '{}'", name)))?;
+                let pose = fields.pose("", Pose::IDENTITY)?;
+                let is_moving = ["end_scale", "end_rotate", "end_translate"]
+                    .iter()
+                    .any(|key| fields.contains(key));
+                let motion = if is_moving {
+                    let end = fields.pose("end_", pose)?;
+                    // Scales are interpolated linearly, so one changing sign
+                    // would pass through zero during the shutter interval.
+                    if (0..3).any(|i| pose.scale.0[i] * end.scale.0[i] < 0.0) {
+                        return Err(fields.error(
+                            "end_scale",
+                            "must have the same sign as scale on every axis",
+                        ));
+                    }
+                    Some(Motion {
+                        start: pose,
+                        end,
+                        start_time: fields.f64_or("start_time", 0.0)?,
+                        end_time: fields.f64_or("end_time", 1.0)?,
+                    })
+                } else {
+                    None
+                };
+                fields.finish()?;
+                self.placements.push(Placement {
+                    object,
+                    transform: pose.transform(),
+                    motion,
+                });
+            }
+            "medium" => {
+                let [shape] = expect_positional(line, &positional, &["shape"])?;
+                let phase_function = self.material(&mut fields)?;
+                let medium: Box<dyn Surface> = if shape == "grid" {
+                    Box::new(self.parse_grid_medium(&mut fields, phase_function)?)
+                } else {
+                    let density = fields.f64("density")?;
+                    if density <= 0.0 {
+                        return Err(fields.error("density", "must be positive"));
+                    }
+                    let boundary =
+                        self.parse_boundary(line, shape, &mut fields, &phase_function)?;
+                    Box::new(ConstantMedium {
+                        boundary,
+                        density,
+                        phase_function,
+                    })
+                };
+                fields.finish()?;
+                self.surfaces.push(medium);
+            }
+            "point_light" => {
+                expect_positional(line, &positional, &[])?;
+                let light = PointLight {
+                    position: fields.v3("position")?,
+                    intensity: fields.v3("intensity")?,
+                };
+                fields.finish()?;
+                self.lights.push(Box::new(light));
+            }
+            "spot_light" => {
+                expect_positional(line, &positional, &[])?;
+                let cone_angle = fields.f64("cone_angle")?;
+                if cone_angle <= 0.0 || cone_angle > 180.0 {
+                    return Err(fields.error("cone_angle", "must be above 0 and at most 180"));
+                }
+                let falloff_angle = fields.f64_or("falloff_angle", cone_angle)?;
+                if falloff_angle < 0.0 || falloff_angle > cone_angle {
+                    return Err(fields.error("falloff_angle", "must be between 0 and cone_angle"));
+                }
+                let light = SpotLight {
+                    position: fields.v3("position")?,
+                    direction: fields.v3("direction")?,
+                    intensity: fields.v3("intensity")?,
+                    cone_angle: cone_angle.to_radians(),
+                    falloff_angle: falloff_angle.to_radians(),
+                };
+                fields.finish()?;
+                self.lights.push(Box::new(light));
+            }
+            "directional_light" => {
+                expect_positional(line, &positional, &[])?;
+                let light = DirectionalLight {
+                    direction: fields.v3("direction")?,
+                    irradiance: fields.v3("irradiance")?,
+                };
+                fields.finish()?;
+                self.lights.push(Box::new(light));
+            }
+            _ => return Err(error(line, format!("unknown statement '{}'", keyword))),
+        }
+        Ok(())
+    }
+
+    fn material(&self, fields: &mut Fields) -> Result<Arc<dyn Material>, SceneError> {
+        let name = fields.required("material")?;
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| fields.error("material", format!("unknown material '{}'", name)))
+    }
+
+    fn path(&self, fields: &mut Fields, key: &str) -> Result<PathBuf, SceneError> {
+        Ok(self.base_directory.join(fields.required(key)?))
+    }
+
+    /// Reads a field holding either a constant color or a texture name.
+    fn texture(&self, fields: &mut Fields, key: &str) -> Result<Arc<dyn Texture>, SceneError> {
+        let value = fields.required(key)?;
+        if let Some(texture) = self.textures.get(value) {
+            return Ok(texture.clone());
+        }
+        if value.contains(',') {
+            return Ok(Arc::new(fields.parse_v3(key, value)?));
+        }
+        Err(fields.error(key, format!("unknown texture '{}'", value)))
+    }
+
+    /// Reads a parameter given either as a single number or as the name of
+    /// a texture, falling back to `default` if missing.
+    fn scalar_texture(
+        &self,
+        fields: &mut Fields,
+        key: &str,
+        default: f64,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        let value = match fields.optional(key) {
+            Some(value) => value,
+            None => return Ok(Arc::new(V3([default; 3]))),
+        };
+        if let Some(texture) = self.textures.get(value) {
+            return Ok(texture.clone());
+        }
+        Ok(Arc::new(V3([fields.parse_f64(key, value)?; 3])))
+    }
+
+    /// Reads the closed surface enclosing a medium, placed by the optional
+    /// `scale`, `rotate` and `translate` fields.
+    fn parse_boundary(
+        &self,
+        line: usize,
+        shape: &str,
+        fields: &mut Fields,
+        material: &Arc<dyn Material>,
+    ) -> Result<Box<dyn Surface>, SceneError> {
+        let boundary: Box<dyn Surface> = match shape {
+            "sphere" => Box::new(Sphere {
+                center: fields.v3("center")?,
+                radius: fields.f64("radius")?,
+                material: material.clone(),
+            }),
+            "box" => {
+                let mut faces = Vec::new();
+                push_box(
+                    &mut faces,
+                    fields.v3("minimum")?,
+                    fields.v3("maximum")?,
+                    material.clone(),
+                );
+                Box::new(faces)
+            }
+            _ => return Err(error(line, format!("unknown medium shape '{}'", shape))),
+        };
+        let is_placed = ["scale", "rotate", "translate"]
+            .iter()
+            .any(|key| fields.contains(key));
+        if !is_placed {
+            return Ok(boundary);
+        }
+        Ok(Box::new(Instance {
+            surface: Arc::from(boundary),
+            transform: fields.pose("", Pose::IDENTITY)?.transform(),
+        }))
+    }
+
+    /// Reads a medium filling the box from `minimum` to `maximum` with voxel
+    /// grids, placed by the optional `scale`, `rotate` and `translate` fields.
+    fn parse_grid_medium(
+        &self,
+        fields: &mut Fields,
+        phase_function: Arc<dyn Material>,
+    ) -> Result<GridMedium, SceneError> {
+        let minimum = fields.v3("minimum")?;
+        let maximum = fields.v3("maximum")?;
+        let size = maximum - minimum;
+        if size.0.iter().any(|&x| x <= 0.0) {
+            return Err(fields.error("maximum", "must be greater than minimum"));
+        }
+        let absorption = self.grid(fields, "absorption")?;
+        let scattering = self.grid(fields, "scattering")?;
+        if absorption.is_none() && scattering.is_none() {
+            return Err(fields.error(
+                "scattering",
+                "a grid medium needs absorption, scattering or both",
+            ));
+        }
+        // Media only emit where they absorb, so emission on its own would
+        // never be seen.
+        if absorption.is_none() && fields.contains("emission") {
+            return Err(fields.error("emission", "needs an absorption grid"));
+        }
+        let emission = self.grid(fields, "emission")?;
+        let emission_color = fields
+            .optional_v3("emission_color")?
+            .unwrap_or(V3([1.0, 1.0, 1.0]));
+        let density = fields.f64_or("density", 1.0)?;
+        if density <= 0.0 {
+            return Err(fields.error("density", "must be positive"));
+        }
+        let transform = Transform::scaling(size)
+            .then(Transform::translation(minimum))
+            .then(fields.pose("", Pose::IDENTITY)?.transform());
+        Ok(GridMedium::new(GridMediumOptions {
+            absorption,
+            scattering,
+            emission,
+            emission_color,
+            density,
+            phase_function,
+            transform,
+        }))
+    }
+
+    fn grid(&self, fields: &mut Fields, key: &str) -> Result<Option<Grid>, SceneError> {
+        if !fields.contains(key) {
+            return Ok(None);
+        }
+        let path = self.path(fields, key)?;
+        grid::load(&path)
+            .map(Some)
+            .map_err(|e| fields.error(key, format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse_environment(
+        &mut self,
+        line: usize,
+        kind: &str,
+        fields: &mut Fields,
+    ) -> Result<Box<dyn Environment>, SceneError> {
+        self.sun = None;
+        Ok(match kind {
+            "none" => Box::new(Constant { color: V3::ZERO }),
+            "constant" => Box::new(Constant {
+                color: fields.v3("color")?,
+            }),
+            "gradient" => {
+                let sky = Gradient::default();
+                Box::new(Gradient {
+                    up: fields.optional_v3("up")?.unwrap_or(sky.up),
+                    down: fields.optional_v3("down")?.unwrap_or(sky.down),
+                })
+            }
+            "image" => {
+                let path = self.path(fields, "path")?;
+                let rotation = fields.f64_or("rotation", 0.0)?.to_radians();
+                let image = hdr::load(&path)
+                    .map_err(|e| fields.error("path", format!("{}: {}", path.display(), e)))?;
+                Box::new(EnvironmentMap::new(image, rotation))
+            }
+            "sky" => {
+                let elevation = fields.f64("sun_elevation")?;
+                if !(0.0..=90.0).contains(&elevation) {
+                    return Err(fields.error(
+                        "sun_elevation",
+                        format!("expected 0 to 90 degrees, got {}", elevation),
+                    ));
+                }
+                let azimuth = fields.f64_or("sun_azimuth", 0.0)?;
+                let turbidity = fields.f64_or("turbidity", 3.0)?;
+                if !(1.7..=10.0).contains(&turbidity) {
+                    return Err(fields.error(
+                        "turbidity",
+                        format!("expected 1.7 to 10, got {}", turbidity),
+                    ));
+                }
+                let sky = PreethamSky::new(elevation.to_radians(), azimuth.to_radians(), turbidity);
+                self.sun = Some(sky.sun_light());
+                Box::new(sky)
+            }
+            _ => return Err(error(line, format!("unknown environment kind '{}'", kind))),
+        })
+    }
+
+    fn parse_texture(
+        &self,
+        line: usize,
+        kind: &str,
+        fields: &mut Fields,
+    ) -> Result<Arc<dyn Texture>, SceneError> {
+        Ok(match kind {
+            "image" => {
+                let path = self.path(fields, "path")?;
+                let wrap = match fields.optional("wrap").unwrap_or("repeat") {
+                    "repeat" => WrapMode::Repeat,
+                    "mirror" => WrapMode::Mirror,
+                    "clamp" => WrapMode::Clamp,
+                    other => {
+                        return Err(fields.error(
+                            "wrap",
+                            format!("expected repeat, mirror or clamp, got '{}'", other),
+                        ))
+                    }
+                };
+                let texture = ImageTexture::load_png(&path, wrap)
+                    .map_err(|e| fields.error("path", format!("{}: {}", path.display(), e)))?;
+                Arc::new(texture)
+            }
+            "checker" => Arc::new(Checker {
+                even: self.texture(fields, "even")?,
+                odd: self.texture(fields, "odd")?,
+                scale: fields.f64_or("scale", 1.0)?,
+            }),
+            "noise" => Arc::new(Noise {
+                perlin: Perlin::new(fields.integer_or("seed", 0)?),
+                scale: fields.f64_or("scale", 1.0)?,
+                color: fields.v3("color")?,
+            }),
+            "turbulence" => Arc::new(Turbulence {
+                perlin: Perlin::new(fields.integer_or("seed", 0)?),
+                scale: fields.f64_or("scale", 1.0)?,
+                octaves: fields.integer_or("octaves", 7)?,
+                color: fields.v3("color")?,
+            }),
+            "marble" => Arc::new(Marble {
+                perlin: Perlin::new(fields.integer_or("seed", 0)?),
+                scale: fields.f64_or("scale", 1.0)?,
+                octaves: fields.integer_or("octaves", 7)?,
+                distortion: fields.f64_or("distortion", 10.0)?,
+                color: fields.v3("color")?,
+            }),
+            "wood" => Arc::new(Wood {
+                perlin: Perlin::new(fields.integer_or("seed", 0)?),
+                scale: fields.f64_or("scale", 1.0)?,
+                rings: fields.f64_or("rings", 8.0)?,
+                distortion: fields.f64_or("distortion", 0.5)?,
+                light: fields.v3("light")?,
+                dark: fields.v3("dark")?,
+            }),
+            _ => return Err(error(line, format!("unknown texture kind '{}'", kind))),
+        })
+    }
+
+    fn parse_material(
+        &self,
+        line: usize,
+        kind: &str,
+        fields: &mut Fields,
+    ) -> Result<Arc<dyn Material>, SceneError> {
+        Ok(match kind {
+            "diffuse" => Arc::new(Diffuse {
+                color: self.texture(fields, "color")?,
+            }),
+            "reflective" => Arc::new(Reflective {
+                color: self.texture(fields, "color")?,
+                fuzz: fields.f64_or("fuzz", 0.0)?,
+            }),
+            "refractive" => Arc::new(Refractive {
+                ratio: fields.ratio("ratio")?,
+                absorption: fields.absorption("absorption")?,
+            }),
+            "dielectric" => Arc::new(Dielectric {
+                ratio: fields.ratio("ratio")?,
+                roughness: fields.roughness("roughness")?,
+                absorption: fields.absorption("absorption")?,
+            }),
+            "principled" => {
+                let ior = fields.f64_or("ior", 1.5)?;
+                if ior < 1.0 {
+                    return Err(fields.error("ior", "must be at least 1"));
+                }
+                Arc::new(Principled {
+                    base_color: self.texture(fields, "base_color")?,
+                    metallic: self.scalar_texture(fields, "metallic", 0.0)?,
+                    roughness: self.scalar_texture(fields, "roughness", 0.5)?,
+                    specular: self.scalar_texture(fields, "specular", 0.5)?,
+                    specular_tint: self.scalar_texture(fields, "specular_tint", 0.0)?,
+                    sheen: self.scalar_texture(fields, "sheen", 0.0)?,
+                    clearcoat: self.scalar_texture(fields, "clearcoat", 0.0)?,
+                    clearcoat_gloss: self.scalar_texture(fields, "clearcoat_gloss", 1.0)?,
+                    transmission: self.scalar_texture(fields, "transmission", 0.0)?,
+                    ior,
+                })
+            }
+            "isotropic" => Arc::new(Isotropic {
+                color: self.texture(fields, "color")?,
+            }),
+            "henyey_greenstein" => {
+                let g = fields.f64_or("g", 0.0)?;
+                if g.abs() >= 1.0 {
+                    return Err(fields.error("g", "must be between -1 and 1"));
+                }
+                Arc::new(HenyeyGreenstein {
+                    color: self.texture(fields, "color")?,
+                    g,
+                })
+            }
+            "diffuse_light" => Arc::new(DiffuseLight {
+                color: fields.v3("color")?,
+            }),
+            "conductor" => {
+                let roughness = fields.roughness("roughness")?;
+                match fields.optional("metal") {
+                    Some(metal) => {
+                        let metal = match metal {
+                            "gold" => Metal::Gold,
+                            "copper" => Metal::Copper,
+                            "aluminium" => Metal::Aluminium,
+                            _ => {
+                                return Err(
+                                    fields.error("metal", format!("unknown metal '{}'", metal))
+                                )
+                            }
+                        };
+                        Arc::new(Conductor::from_metal(metal, roughness))
+                    }
+                    None => Arc::new(Conductor {
+                        eta: fields.v3("eta")?,
+                        k: fields.v3("k")?,
+                        roughness,
+                    }),
+                }
+            }
+            _ => return Err(error(line, format!("unknown material kind '{}'", kind))),
+        })
+    }
+}
+
+fn error(line: usize, message: String) -> SceneError {
+    SceneError::Parse {
+        line,
+        field: None,
+        message,
+    }
+}
+
+fn expect_positional<'a, const N: usize>(
+    line: usize,
+    positional: &[&'a str],
+    names: &[&str; N],
+) -> Result<[&'a str; N], SceneError> {
+    positional.try_into().map_err(|_| {
+        let expected = if N == 0 {
+            String::from("no arguments before the fields")
+        } else {
+            names.join(" ")
+        };
+        error(
+            line,
+            format!("expected {}, got '{}'", expected, positional.join(" ")),
+        )
+    })
+}
+
+/// The `field=value` pairs of a statement, tracking which have been read so
+/// that misspelled fields are reported rather than silently ignored.
+struct Fields<'a> {
+    line: usize,
+    values: Vec<(&'a str, &'a str, bool)>,
+}
+
+impl<'a> Fields<'a> {
+    fn error(&self, key: &str, message: impl fmt::Display) -> SceneError {
+        SceneError::Parse {
+            line: self.line,
+            field: Some(key.to_string()),
+            message: message.to_string(),
+        }
+    }
+
+    fn optional(&mut self, key: &str) -> Option<&'a str> {
+        self.values
+            .iter_mut()
+            .find(|(other, _, _)| *other == key)
+            .map(|(_, value, used)| {
+                *used = true;
+                *value
+            })
+    }
+
+    fn required(&mut self, key: &str) -> Result<&'a str, SceneError> {
+        self.optional(key)
+            .ok_or_else(|| self.error(key, "missing required field"))
+    }
+
+    fn parse_f64(&self, key: &str, value: &str) -> Result<f64, SceneError> {
+        match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(number),
+            Ok(_) => Err(self.error(key, format!("expected a finite number, got '{}'", value))),
+            Err(_) => Err(self.error(key, format!("expected a number, got '{}'", value))),
+        }
+    }
+
+    fn parse_v3(&self, key: &str, value: &str) -> Result<V3, SceneError> {
+        let components = value
+            .split(',')
+            .map(|component| self.parse_f64(key, component))
+            .collect::<Result<Vec<f64>, _>>()?;
+        match components[..] {
+            [x, y, z] => Ok(V3([x, y, z])),
+            _ => Err(self.error(key, format!("expected x,y,z, got '{}'", value))),
+        }
+    }
+
+    fn f64(&mut self, key: &str) -> Result<f64, SceneError> {
+        let value = self.required(key)?;
+        self.parse_f64(key, value)
+    }
+
+    fn optional_f64(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        match self.optional(key) {
+            Some(value) => self.parse_f64(key, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn f64_or(&mut self, key: &str, default: f64) -> Result<f64, SceneError> {
+        Ok(self.optional_f64(key)?.unwrap_or(default))
+    }
+
+    fn integer_or<T: FromStr>(&mut self, key: &str, default: T) -> Result<T, SceneError> {
+        match self.optional(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| self.error(key, format!("expected a whole number, got '{}'", value))),
+            None => Ok(default),
+        }
+    }
+
+    /// Reads a ratio of refractive indices, which has to be positive.
+    fn ratio(&mut self, key: &str) -> Result<f64, SceneError> {
+        let ratio = self.f64(key)?;
+        if ratio <= 0.0 {
+            return Err(self.error(key, "must be positive"));
+        }
+        Ok(ratio)
+    }
+
+    /// Reads an absorption coefficient per color channel, defaulting to 0.
+    fn absorption(&mut self, key: &str) -> Result<V3, SceneError> {
+        let absorption = self.optional_v3(key)?.unwrap_or(V3::ZERO);
+        if absorption.0.iter().any(|&x| x < 0.0) {
+            return Err(self.error(key, "must not be negative"));
+        }
+        Ok(absorption)
+    }
+
+    /// Reads a roughness given either once for both tangent directions or as
+    /// two comma-separated values, defaulting to 0.
+    fn roughness(&mut self, key: &str) -> Result<[f64; 2], SceneError> {
+        let value = match self.optional(key) {
+            Some(value) => value,
+            None => return Ok([0.0, 0.0]),
+        };
+        let components = value
+            .split(',')
+            .map(|component| self.parse_f64(key, component))
+            .collect::<Result<Vec<f64>, _>>()?;
+        let roughness = match components[..] {
+            [r] => [r, r],
+            [u, v] => [u, v],
+            _ => {
+                return Err(self.error(key, format!("expected one or two numbers, got '{}'", value)))
+            }
+        };
+        if roughness.iter().any(|r| !(0.0..=1.0).contains(r)) {
+            return Err(self.error(key, "must be between 0 and 1"));
+        }
+        Ok(roughness)
+    }
+
+    fn v3(&mut self, key: &str) -> Result<V3, SceneError> {
+        let value = self.required(key)?;
+        self.parse_v3(key, value)
+    }
+
+    fn optional_v3(&mut self, key: &str) -> Result<Option<V3>, SceneError> {
+        match self.optional(key) {
+            Some(value) => self.parse_v3(key, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads `scale`, given once for all axes or per axis, `rotate` by
+    /// degrees around the x, y and z axes in turn and `translate`, each
+    /// prefixed by `prefix` and falling back to `default`.
+    fn pose(&mut self, prefix: &str, default: Pose) -> Result<Pose, SceneError> {
+        let key = format!("{}scale", prefix);
+        let scale = match self.optional(&key) {
+            Some(value) if value.contains(',') => self.parse_v3(&key, value)?,
+            Some(value) => V3([self.parse_f64(&key, value)?; 3]),
+            None => default.scale,
+        };
+        if scale.0.contains(&0.0) {
+            return Err(self.error(&key, "must not be zero"));
+        }
+        let rotation = match self.optional_v3(&format!("{}rotate", prefix))? {
+            Some(degrees) => degrees.map(f64::to_radians),
+            None => default.rotation,
+        };
+        let translation = self
+            .optional_v3(&format!("{}translate", prefix))?
+            .unwrap_or(default.translation);
+        Ok(Pose {
+            scale,
+            rotation,
+            translation,
+        })
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.values.iter().any(|(other, _, _)| *other == key)
+    }
+
+    fn finish(&self) -> Result<(), SceneError> {
+        match self.values.iter().find(|(_, _, used)| !used) {
+            Some((key, _, _)) => Err(self.error(key, "unknown field")),
+            None => Ok(()),
+        }
+    }
+}
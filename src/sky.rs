@@ -0,0 +1,161 @@
+//! Daylight after Preetham, Shirley and Smits, "A Practical Analytic Model
+//! for Daylight" (1999).
+
+use std::f64::consts::PI;
+
+use crate::environment::Environment;
+use crate::light::DirectionalLight;
+use crate::v3::V3;
+
+/// Scale from luminance in kcd/m² to the radiance units of the renderer,
+/// chosen so that a white surface in midday sun is about 1.
+const LUMINANCE_SCALE: f64 = 0.04;
+/// Luminance of the sun outside the atmosphere, in kcd/m².
+const SUN_LUMINANCE: f64 = 2.0e6;
+/// Angular radius of the sun disk, in radians.
+const SUN_RADIUS: f64 = 0.004_65;
+/// Wavelengths standing in for the red, green and blue channels, in µm.
+const WAVELENGTHS: [f64; 3] = [0.68, 0.55, 0.44];
+
+/// Clear sky lit by a sun in `sun_direction`, with haze given by `turbidity`
+/// (2 for a very clear sky, around 10 for a hazy one).
+pub struct PreethamSky {
+    sun_direction: V3,
+    sun_radiance: V3,
+    /// Perez coefficients for the luminance and the two chromaticities.
+    coefficients: [[f64; 5]; 3],
+    /// Luminance and chromaticities at the zenith divided by the Perez
+    /// function there, so that multiplying by it gives absolute values.
+    zenith: [f64; 3],
+}
+
+impl PreethamSky {
+    /// Creates a sky for the sun at `elevation` above the horizon and
+    /// `azimuth` around the y axis from +x towards +z, both in radians.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> PreethamSky {
+        let elevation = elevation.clamp(0.0, PI / 2.0);
+        let sun_direction = V3([
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        ]);
+        let t = turbidity;
+        let coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta = PI / 2.0 - elevation;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let polynomial = |[a, b, c, d]: [f64; 4]| ((a * theta + b) * theta + c) * theta + d;
+        let zenith_x = t * t * polynomial([0.00166, -0.00375, 0.00209, 0.0])
+            + t * polynomial([-0.02903, 0.06377, -0.03202, 0.00394])
+            + polynomial([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y = t * t * polynomial([0.00275, -0.00610, 0.00317, 0.0])
+            + t * polynomial([-0.04214, 0.08970, -0.04153, 0.00516])
+            + polynomial([0.15346, -0.26756, 0.06670, 0.26688]);
+        let zenith_values = [zenith_luminance, zenith_x, zenith_y];
+        let zenith = [0, 1, 2].map(|i| zenith_values[i] / perez(coefficients[i], theta, 0.0));
+
+        PreethamSky {
+            sun_direction,
+            sun_radiance: sun_radiance(theta, turbidity),
+            coefficients,
+            zenith,
+        }
+    }
+
+    /// Directional light with the irradiance of the sun disk, to sample it
+    /// directly.
+    pub fn sun_light(&self) -> DirectionalLight {
+        let solid_angle = 2.0 * PI * (1.0 - SUN_RADIUS.cos());
+        DirectionalLight {
+            direction: -self.sun_direction,
+            irradiance: self.sun_radiance * solid_angle,
+        }
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: V3) -> V3 {
+        // The model only covers the upper hemisphere; below the horizon, the
+        // horizon continues.
+        let direction = direction.normalize();
+        let V3([x, y, z]) = direction;
+        let direction = V3([x, y.max(0.001), z]).normalize();
+        let cos_theta = direction.y();
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let theta = cos_theta.acos();
+        let [luminance, x, y] =
+            [0, 1, 2].map(|i| self.zenith[i] * perez(self.coefficients[i], gamma, theta));
+        xyy_to_rgb(luminance * LUMINANCE_SCALE, x, y)
+    }
+
+    fn visible_radiance(&self, direction: V3) -> V3 {
+        let sky = self.radiance(direction);
+        if direction.normalize().dot(self.sun_direction) >= SUN_RADIUS.cos() {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+}
+
+/// Perez et al. distribution of sky luminance for a direction at `theta`
+/// from the zenith and `gamma` from the sun.
+fn perez([a, b, c, d, e]: [f64; 5], gamma: f64, theta: f64) -> f64 {
+    let cos_gamma = gamma.cos();
+    (1.0 + a * (b / theta.cos().max(0.001)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+/// Radiance of the sun disk after Rayleigh and aerosol extinction along the
+/// path through the atmosphere, for the sun at `theta` from the zenith.
+fn sun_radiance(theta: f64, turbidity: f64) -> V3 {
+    let theta_degrees = theta.to_degrees();
+    let air_mass = 1.0 / (theta.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    // Ångström's formula with the wavelength exponent of 1.3 used by the model.
+    let beta = 0.04608 * turbidity - 0.04586;
+    let transmittance = WAVELENGTHS.map(|lambda| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+        rayleigh * aerosol
+    });
+    V3(transmittance) * (SUN_LUMINANCE * LUMINANCE_SCALE)
+}
+
+/// Converts CIE xyY to linear sRGB.
+fn xyy_to_rgb(luminance: f64, x: f64, y: f64) -> V3 {
+    if y <= 0.0 {
+        return V3::ZERO;
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    V3([
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    ])
+    .map(|c| c.max(0.0))
+}
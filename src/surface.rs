@@ -1,23 +1,114 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+
 use crate::bounding_box::BoundingBox;
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::ray_hit::RayHit;
+use crate::util::{orthonormal_basis, random_unit_vector};
 use crate::v3::V3;
 
+/// Smallest distance at which a ray cast to evaluate a sampling density can
+/// hit the sampled surface.
+pub const PDF_T_MIN: f64 = 0.00001;
+
 #[derive(Clone, Copy)]
 pub struct RayHitMaterial<'m> {
     pub hit: RayHit,
     pub material: &'m dyn Material,
+    /// The primitive that was hit, for looking up its density as a light.
+    pub surface: &'m dyn Surface,
 }
 
 pub trait Surface: Send + Sync {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial>;
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>>;
     fn calculate_bounding_box(&self) -> BoundingBox;
+
+    /// Like `hit`, but also finds hits decided at random, such as where a ray
+    /// scatters inside a medium, drawing from the path's `rng` so that such
+    /// decisions are independent of each other.
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        _rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    /// Whether the surface emits light and supports `random_direction`, so
+    /// that it can be sampled directly as a light.
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    /// Density per unit solid angle of `random_direction` picking
+    /// `direction` from `origin`.
+    fn pdf_value(&self, _origin: V3, _direction: V3) -> f64 {
+        0.0
+    }
+
+    /// Picks a direction from `origin` towards a random point of the surface.
+    fn random_direction(&self, _origin: V3, _rng: &mut SmallRng) -> V3 {
+        V3::ZERO
+    }
+
+    /// Fraction of the light travelling along `ray` between `t_min` and
+    /// `t_max` that gets past the surface, for shadow rays: none if it is
+    /// hit, except for media that light partly passes through. Estimates
+    /// draw from `rng` like `sample_hit`.
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, rng: &mut SmallRng) -> f64 {
+        if self.sample_hit(ray, t_min, t_max, rng).is_some() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+impl<T: Surface + ?Sized> Surface for Box<T> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        (**self).hit(ray, t_min, t_max)
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        (**self).calculate_bounding_box()
+    }
+
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        (**self).sample_hit(ray, t_min, t_max, rng)
+    }
+
+    fn is_light(&self) -> bool {
+        (**self).is_light()
+    }
+
+    fn pdf_value(&self, origin: V3, direction: V3) -> f64 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn random_direction(&self, origin: V3, rng: &mut SmallRng) -> V3 {
+        (**self).random_direction(origin, rng)
+    }
+
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, rng: &mut SmallRng) -> f64 {
+        (**self).transmittance(ray, t_min, t_max, rng)
+    }
 }
 
-impl<T: Surface + ?Sized> Surface for Vec<Box<T>> {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial> {
-        let mut nearest_result: Option<RayHitMaterial> = None;
+impl<T: Surface> Surface for Vec<T> {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        let mut nearest_result: Option<RayHitMaterial<'_>> = None;
 
         for surface in self.iter() {
             let nearest_t = nearest_result.map_or(t_max, |hit| hit.hit.t);
@@ -29,6 +120,25 @@ impl<T: Surface + ?Sized> Surface for Vec<Box<T>> {
         nearest_result
     }
 
+    fn sample_hit(
+        &self,
+        ray: Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut SmallRng,
+    ) -> Option<RayHitMaterial<'_>> {
+        let mut nearest_result: Option<RayHitMaterial<'_>> = None;
+
+        for surface in self.iter() {
+            let nearest_t = nearest_result.map_or(t_max, |hit| hit.hit.t);
+            let result = surface.sample_hit(ray, t_min, nearest_t, rng);
+            if result.is_some() {
+                nearest_result = result;
+            }
+        }
+        nearest_result
+    }
+
     fn calculate_bounding_box(&self) -> BoundingBox {
         self.iter()
             .map(|surface| surface.calculate_bounding_box())
@@ -43,54 +153,394 @@ impl<T: Surface + ?Sized> Surface for Vec<Box<T>> {
 pub struct Sphere {
     pub center: V3,
     pub radius: f64,
-    pub material: Box<dyn Material>,
+    pub material: Arc<dyn Material>,
 }
 
 impl Surface for Sphere {
-    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial> {
-        let center = ray.origin - self.center;
-        let a = ray.direction.length_squared();
-        let b_half = center.dot(ray.direction);
-        let c = center.length_squared() - self.radius * self.radius;
-
-        let discriminant = b_half * b_half - a * c;
-        if discriminant >= 0.0 {
-            let discriminant_sqrt = discriminant.sqrt();
-            let mut root = (-b_half - discriminant_sqrt) / a;
-            if root < t_min || root > t_max {
-                root = (-b_half + discriminant_sqrt) / a;
-            }
-            if root < t_min || root > t_max {
-                None
-            } else {
-                let t = root;
-                let position = ray.at(t);
-                // TODO: move to RayHit?
-                let mut normal = (position - self.center) * (1.0 / self.radius);
-                let on_front_face = ray.direction.dot(normal) < 0.0;
-                if !on_front_face {
-                    normal = normal * -1.0
-                }
-                Some(RayHitMaterial {
-                    hit: RayHit {
-                        position,
-                        normal,
-                        t,
-                        on_front_face,
-                    },
-                    material: &(*self.material),
-                })
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        Some(RayHitMaterial {
+            hit: sphere_hit(ray, self.center, self.radius, t_min, t_max)?,
+            material: &(*self.material),
+            surface: self,
+        })
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        let radius = V3([self.radius, self.radius, self.radius]);
+        BoundingBox {
+            minimum: self.center - radius,
+            maximum: self.center + radius,
+        }
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn pdf_value(&self, origin: V3, direction: V3) -> f64 {
+        let ray = Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+        let hit = self.hit(ray, PDF_T_MIN, f64::INFINITY);
+        match self.cone_cos_max(origin) {
+            Some(cos_max) if hit.is_some() => 1.0 / (2.0 * PI * (1.0 - cos_max)),
+            Some(_) => 0.0,
+            None => area_pdf(ray, hit, 4.0 * PI * self.radius * self.radius),
+        }
+    }
+
+    fn random_direction(&self, origin: V3, rng: &mut SmallRng) -> V3 {
+        match self.cone_cos_max(origin) {
+            Some(cos_max) => {
+                // Uniformly within the cone of directions that see the sphere.
+                let axis = (self.center - origin).normalize();
+                let (tangent, bitangent) = orthonormal_basis(axis);
+                let cos = 1.0 + rng.gen::<f64>() * (cos_max - 1.0);
+                let sin = (1.0 - cos * cos).max(0.0).sqrt();
+                let phi = 2.0 * PI * rng.gen::<f64>();
+                tangent * (sin * phi.cos()) + bitangent * (sin * phi.sin()) + axis * cos
             }
+            None => self.center + random_unit_vector(rng) * self.radius - origin,
+        }
+    }
+}
+
+impl Sphere {
+    /// Cosine of the half-angle of the cone that the sphere subtends from
+    /// `origin`, or `None` from inside the sphere.
+    fn cone_cos_max(&self, origin: V3) -> Option<f64> {
+        let distance_squared = (self.center - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared > radius_squared {
+            Some((1.0 - radius_squared / distance_squared).sqrt())
         } else {
             None
         }
     }
 
+    /// Maps the outward unit normal to longitude (`u`, starting from -x and
+    /// going around y) and latitude (`v`, from -y to +y).
+    fn uv(normal: V3) -> [f64; 2] {
+        let V3([x, y, z]) = normal;
+        let u = ((-z).atan2(x) + PI) / (2.0 * PI);
+        let v = (-y).clamp(-1.0, 1.0).acos() / PI;
+        [u, v]
+    }
+}
+
+/// Sphere moving at a constant speed from `start_center` at `start_time` to
+/// `end_center` at `end_time`, resting there before and after.
+///
+/// Unlike `Sphere`, it is never sampled directly as a light.
+pub struct MovingSphere {
+    pub start_center: V3,
+    pub end_center: V3,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f64) -> V3 {
+        if self.end_time <= self.start_time {
+            return self.start_center;
+        }
+        let t = ((time - self.start_time) / (self.end_time - self.start_time)).clamp(0.0, 1.0);
+        self.start_center + (self.end_center - self.start_center) * t
+    }
+}
+
+impl Surface for MovingSphere {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        Some(RayHitMaterial {
+            hit: sphere_hit(ray, self.center(ray.time), self.radius, t_min, t_max)?,
+            material: &(*self.material),
+            surface: self,
+        })
+    }
+
     fn calculate_bounding_box(&self) -> BoundingBox {
         let radius = V3([self.radius, self.radius, self.radius]);
-        BoundingBox {
-            minimum: self.center - radius,
-            maximum: self.center + radius,
+        let [start, end] = [self.start_center, self.end_center].map(|center| BoundingBox {
+            minimum: center - radius,
+            maximum: center + radius,
+        });
+        start.union(end)
+    }
+}
+
+/// Hit of `ray` on the sphere around `center`.
+fn sphere_hit(ray: Ray, center: V3, radius: f64, t_min: f64, t_max: f64) -> Option<RayHit> {
+    let offset = ray.origin - center;
+    let a = ray.direction.length_squared();
+    let b_half = offset.dot(ray.direction);
+    let c = offset.length_squared() - radius * radius;
+
+    let discriminant = b_half * b_half - a * c;
+    if discriminant >= 0.0 {
+        let discriminant_sqrt = discriminant.sqrt();
+        let mut root = (-b_half - discriminant_sqrt) / a;
+        if root < t_min || root > t_max {
+            root = (-b_half + discriminant_sqrt) / a;
+        }
+        if root < t_min || root > t_max {
+            None
+        } else {
+            let t = root;
+            let position = ray.at(t);
+            // TODO: move to RayHit?
+            let mut normal = (position - center) * (1.0 / radius);
+            let uv = Sphere::uv(normal);
+            let tangent = V3([normal.0[2], 0.0, -normal.0[0]]);
+            let on_front_face = ray.direction.dot(normal) < 0.0;
+            if !on_front_face {
+                normal = normal * -1.0
+            }
+            Some(RayHit {
+                position,
+                normal,
+                t,
+                on_front_face,
+                barycentric: None,
+                uv,
+                tangent,
+            })
+        }
+    } else {
+        None
+    }
+}
+
+/// Parallelogram spanned by `u` and `v` from `corner`, facing `u × v`.
+pub struct Quad {
+    pub corner: V3,
+    pub u: V3,
+    pub v: V3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Surface for Quad {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        let n = self.u.cross(self.v);
+        let normal = n.normalize();
+        let denominator = normal.dot(ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+        let t = normal.dot(self.corner - ray.origin) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let position = ray.at(t);
+        let planar = position - self.corner;
+        let w = n * (1.0 / n.dot(n));
+        let alpha = w.dot(planar.cross(self.v));
+        let beta = w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let on_front_face = denominator < 0.0;
+        Some(RayHitMaterial {
+            hit: RayHit {
+                position,
+                normal: if on_front_face { normal } else { -normal },
+                t,
+                on_front_face,
+                barycentric: None,
+                uv: [alpha, beta],
+                tangent: self.u,
+            },
+            material: &(*self.material),
+            surface: self,
+        })
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_points(&[
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ])
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn pdf_value(&self, origin: V3, direction: V3) -> f64 {
+        let ray = Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+        let area = self.u.cross(self.v).length();
+        area_pdf(ray, self.hit(ray, PDF_T_MIN, f64::INFINITY), area)
+    }
+
+    fn random_direction(&self, origin: V3, rng: &mut SmallRng) -> V3 {
+        self.corner + self.u * rng.gen::<f64>() + self.v * rng.gen::<f64>() - origin
+    }
+}
+
+pub struct Triangle {
+    pub vertices: [V3; 3],
+    /// Per-vertex normals for smooth shading; the face normal is used if absent.
+    pub normals: Option<[V3; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+impl Surface for Triangle {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<RayHitMaterial<'_>> {
+        let (t, barycentric) = intersect_triangle(ray, self.vertices, t_min, t_max)?;
+        Some(RayHitMaterial {
+            hit: triangle_hit(ray, t, barycentric, self.vertices, self.normals, None),
+            material: &(*self.material),
+            surface: self,
+        })
+    }
+
+    fn calculate_bounding_box(&self) -> BoundingBox {
+        BoundingBox::from_points(&self.vertices)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    fn pdf_value(&self, origin: V3, direction: V3) -> f64 {
+        let ray = Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+        let hit = self.hit(ray, PDF_T_MIN, f64::INFINITY);
+        area_pdf(ray, hit, triangle_area(self.vertices))
+    }
+
+    fn random_direction(&self, origin: V3, rng: &mut SmallRng) -> V3 {
+        random_triangle_point(self.vertices, rng) - origin
+    }
+}
+
+/// Converts the density of picking a uniformly random point on a surface
+/// with `area` to a density per unit solid angle at the origin of `ray`,
+/// given where the ray hits the surface.
+pub fn area_pdf(ray: Ray, hit: Option<RayHitMaterial<'_>>, area: f64) -> f64 {
+    match hit {
+        Some(result) => {
+            let distance_squared = result.hit.t * result.hit.t * ray.direction.length_squared();
+            let cos = result.hit.normal.dot(ray.direction).abs() / ray.direction.length();
+            distance_squared / (cos * area)
+        }
+        None => 0.0,
+    }
+}
+
+pub fn triangle_area([p0, p1, p2]: [V3; 3]) -> f64 {
+    0.5 * (p1 - p0).cross(p2 - p0).length()
+}
+
+/// Picks a point uniformly distributed over the area of the triangle.
+pub fn random_triangle_point([p0, p1, p2]: [V3; 3], rng: &mut SmallRng) -> V3 {
+    let r = rng.gen::<f64>().sqrt();
+    let s = rng.gen::<f64>();
+    p0 * (1.0 - r) + p1 * (r * (1.0 - s)) + p2 * (r * s)
+}
+
+/// Möller–Trumbore ray-triangle intersection, returning `t` and the
+/// barycentric coordinates of the hit.
+pub fn intersect_triangle(
+    ray: Ray,
+    [p0, p1, p2]: [V3; 3],
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, [f64; 2])> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let determinant_inv = 1.0 / determinant;
+
+    let s = ray.origin - p0;
+    let u = s.dot(p) * determinant_inv;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) * determinant_inv;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q) * determinant_inv;
+    if t < t_min || t > t_max {
+        None
+    } else {
+        Some((t, [u, v]))
+    }
+}
+
+/// Builds the hit record for a triangle, facing the geometric normal against
+/// the ray and interpolating the shading normal from `normals` and texture
+/// coordinates from `uvs` if given. Without `uvs`, the barycentric
+/// coordinates serve as texture coordinates.
+pub fn triangle_hit(
+    ray: Ray,
+    t: f64,
+    barycentric: [f64; 2],
+    [p0, p1, p2]: [V3; 3],
+    normals: Option<[V3; 3]>,
+    uvs: Option<[[f64; 2]; 3]>,
+) -> RayHit {
+    let [u, v] = barycentric;
+    let face_normal = (p1 - p0).cross(p2 - p0).normalize();
+    let on_front_face = ray.direction.dot(face_normal) < 0.0;
+    let mut normal = match normals {
+        Some([n0, n1, n2]) => (n0 * (1.0 - u - v) + n1 * u + n2 * v).normalize(),
+        None => face_normal,
+    };
+    if !on_front_face {
+        normal = -normal
+    }
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let (uv, tangent) = match uvs {
+        Some([uv0, uv1, uv2]) => {
+            let w = 1.0 - u - v;
+            let uv = [
+                uv0[0] * w + uv1[0] * u + uv2[0] * v,
+                uv0[1] * w + uv1[1] * u + uv2[1] * v,
+            ];
+            // Solves for the change in position per unit change in uv[0]
+            // along the edges.
+            let [du1, dv1] = [uv1[0] - uv0[0], uv1[1] - uv0[1]];
+            let [du2, dv2] = [uv2[0] - uv0[0], uv2[1] - uv0[1]];
+            let determinant = du1 * dv2 - dv1 * du2;
+            let tangent = if determinant.abs() < 1e-12 {
+                edge1
+            } else {
+                (edge1 * dv2 - edge2 * dv1) * (1.0 / determinant)
+            };
+            (uv, tangent)
         }
+        None => (barycentric, edge1),
+    };
+    RayHit {
+        position: ray.at(t),
+        normal,
+        t,
+        on_front_face,
+        barycentric: Some(barycentric),
+        uv,
+        tangent,
     }
 }
@@ -0,0 +1,270 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use std::sync::Arc;
+
+use png::HasParameters;
+
+use crate::perlin::Perlin;
+use crate::v3::V3;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, uv: [f64; 2], position: V3) -> V3;
+}
+
+/// A constant color.
+impl Texture for V3 {
+    fn value(&self, _uv: [f64; 2], _position: V3) -> V3 {
+        *self
+    }
+}
+
+/// Alternates between two textures in a 3D grid of cubes with edges of
+/// `scale`, so that it does not depend on texture coordinates.
+pub struct Checker {
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+    pub scale: f64,
+}
+
+impl Texture for Checker {
+    fn value(&self, uv: [f64; 2], position: V3) -> V3 {
+        let V3(cell) = position.map(|x| (x / self.scale).floor());
+        if ((cell[0] + cell[1] + cell[2]) as i64).rem_euclid(2) == 0 {
+            self.even.value(uv, position)
+        } else {
+            self.odd.value(uv, position)
+        }
+    }
+}
+
+/// Perlin noise mapped to `[0, 1]` and tinted by `color`.
+pub struct Noise {
+    pub perlin: Perlin,
+    pub scale: f64,
+    pub color: V3,
+}
+
+impl Texture for Noise {
+    fn value(&self, _uv: [f64; 2], position: V3) -> V3 {
+        self.color * (0.5 * (1.0 + self.perlin.noise(position * self.scale)))
+    }
+}
+
+pub struct Turbulence {
+    pub perlin: Perlin,
+    pub scale: f64,
+    pub octaves: usize,
+    pub color: V3,
+}
+
+impl Texture for Turbulence {
+    fn value(&self, _uv: [f64; 2], position: V3) -> V3 {
+        self.color * self.perlin.turbulence(position * self.scale, self.octaves)
+    }
+}
+
+/// Bands along the z axis distorted by turbulence.
+pub struct Marble {
+    pub perlin: Perlin,
+    pub scale: f64,
+    pub octaves: usize,
+    /// How far the turbulence shifts the bands.
+    pub distortion: f64,
+    pub color: V3,
+}
+
+impl Texture for Marble {
+    fn value(&self, _uv: [f64; 2], position: V3) -> V3 {
+        let position = position * self.scale;
+        let V3([_, _, z]) = position;
+        let phase = z + self.distortion * self.perlin.turbulence(position, self.octaves);
+        self.color * (0.5 * (1.0 + phase.sin()))
+    }
+}
+
+/// Growth rings around the y axis, with their radii perturbed by noise.
+pub struct Wood {
+    pub perlin: Perlin,
+    pub scale: f64,
+    /// Number of rings per unit of distance from the axis.
+    pub rings: f64,
+    /// How far the noise shifts the rings.
+    pub distortion: f64,
+    pub light: V3,
+    pub dark: V3,
+}
+
+impl Texture for Wood {
+    fn value(&self, _uv: [f64; 2], position: V3) -> V3 {
+        let V3([x, _, z]) = position;
+        let radius = (x * x + z * z).sqrt();
+        let ring = radius * self.rings + self.distortion * self.perlin.noise(position * self.scale);
+        let t = ring - ring.floor();
+        self.light * (1.0 - t) + self.dark * t
+    }
+}
+
+/// How texture coordinates outside of `[0, 1]` are mapped onto the image.
+#[derive(Clone, Copy)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl WrapMode {
+    fn apply(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Mirror => {
+                let j = i.rem_euclid(2 * size);
+                if j < size {
+                    j
+                } else {
+                    2 * size - 1 - j
+                }
+            }
+            WrapMode::Clamp => i.clamp(0, size - 1),
+        };
+        wrapped as usize
+    }
+}
+
+/// Bilinearly filtered image, with `v` running from the bottom row up.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<V3>,
+    pub wrap: WrapMode,
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    Decoding(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(error) => write!(f, "{}", error),
+            ImageError::Decoding(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<io::Error> for ImageError {
+    fn from(error: io::Error) -> ImageError {
+        ImageError::Io(error)
+    }
+}
+
+impl From<png::DecodingError> for ImageError {
+    fn from(error: png::DecodingError) -> ImageError {
+        match error {
+            png::DecodingError::IoError(error) => ImageError::Io(error),
+            error => ImageError::Decoding(error.to_string()),
+        }
+    }
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<V3>, wrap: WrapMode) -> ImageTexture {
+        assert_eq!(pixels.len(), width * height);
+        ImageTexture {
+            width,
+            height,
+            pixels,
+            wrap,
+        }
+    }
+
+    /// Loads a PNG file, converting its sRGB colors to linear ones.
+    pub fn load_png(path: &Path, wrap: WrapMode) -> Result<ImageTexture, ImageError> {
+        let file = File::open(path)?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set(png::Transformations::EXPAND);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut bytes = vec![0; info.buffer_size()];
+        reader.next_frame(&mut bytes)?;
+
+        let channels = match info.color_type {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::RGB => 3,
+            png::ColorType::RGBA => 4,
+            png::ColorType::Indexed => {
+                return Err(ImageError::Decoding(String::from(
+                    "unexpanded indexed colors",
+                )))
+            }
+        };
+        let samples: Vec<f64> = match info.bit_depth {
+            png::BitDepth::Eight => bytes.iter().map(|&x| x as f64 / 255.0).collect(),
+            png::BitDepth::Sixteen => bytes
+                .chunks(2)
+                .map(|x| u16::from_be_bytes([x[0], x[1]]) as f64 / 65535.0)
+                .collect(),
+            _ => {
+                return Err(ImageError::Decoding(String::from(
+                    "unexpanded bit depth below 8",
+                )))
+            }
+        };
+
+        let width = info.width as usize;
+        let height = info.height as usize;
+        let row_samples = width * channels;
+        let row_stride = samples.len() / height;
+        let pixels = samples
+            .chunks(row_stride)
+            .flat_map(|row| row[..row_samples].chunks(channels))
+            .map(|pixel| {
+                let color = if channels < 3 {
+                    V3([pixel[0], pixel[0], pixel[0]])
+                } else {
+                    V3([pixel[0], pixel[1], pixel[2]])
+                };
+                color.map(srgb_to_linear)
+            })
+            .collect();
+        Ok(ImageTexture::new(width, height, pixels, wrap))
+    }
+
+    fn texel(&self, x: i64, y: i64) -> V3 {
+        let x = self.wrap.apply(x, self.width);
+        let y = self.wrap.apply(y, self.height);
+        self.pixels[x + y * self.width]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, [u, v]: [f64; 2], _position: V3) -> V3 {
+        // Texel centers lie at half-integer coordinates.
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0) * (1.0 - fx) + self.texel(x0 + 1, y0) * fx;
+        let bottom = self.texel(x0, y0 + 1) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
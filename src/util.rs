@@ -1,7 +1,83 @@
 use crate::v3::V3;
-use rand::thread_rng;
+use rand::rngs::SmallRng;
 use rand_distr::{Distribution, UnitSphere};
 
-pub fn random_unit_vector() -> V3 {
-    V3(UnitSphere.sample(&mut thread_rng()))
+pub fn random_unit_vector(rng: &mut SmallRng) -> V3 {
+    V3(UnitSphere.sample(rng))
+}
+
+/// Relative luminance of a linear sRGB color.
+pub fn luminance(color: V3) -> f64 {
+    let V3([r, g, b]) = color;
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Derives an independent seed for the `index`th random stream of a render
+/// seeded with `seed`, using the SplitMix64 finalizer.
+pub fn mix_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Two unit vectors completing the unit `normal` to an orthonormal basis,
+/// using the branchless construction of Duff et al.
+pub fn orthonormal_basis(normal: V3) -> (V3, V3) {
+    let V3([x, y, z]) = normal;
+    let sign = 1.0_f64.copysign(z);
+    let a = -1.0 / (sign + z);
+    let b = x * y * a;
+    (
+        V3([1.0 + sign * x * x * a, sign * b, -sign * x]),
+        V3([b, sign + y * y * a, -y]),
+    )
+}
+
+/// Orthonormal basis around a surface normal, for working with directions
+/// in coordinates where the normal is the z axis.
+#[derive(Clone, Copy)]
+pub struct Frame {
+    pub tangent: V3,
+    pub bitangent: V3,
+    pub normal: V3,
+}
+
+impl Frame {
+    pub fn from_normal(normal: V3) -> Frame {
+        let (tangent, bitangent) = orthonormal_basis(normal);
+        Frame {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    /// Frame whose tangent follows `tangent` where it isn't parallel to
+    /// `normal`, so that anisotropy stays put on the surface.
+    pub fn from_normal_and_tangent(normal: V3, tangent: V3) -> Frame {
+        let tangent = tangent - normal * normal.dot(tangent);
+        if tangent.length_squared() < 1e-12 * normal.length_squared() {
+            return Frame::from_normal(normal);
+        }
+        let tangent = tangent.normalize();
+        Frame {
+            tangent,
+            bitangent: normal.cross(tangent),
+            normal,
+        }
+    }
+
+    pub fn to_local(&self, v: V3) -> V3 {
+        V3([
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        ])
+    }
+
+    pub fn to_world(&self, v: V3) -> V3 {
+        let V3([x, y, z]) = v;
+        self.tangent * x + self.bitangent * y + self.normal * z
+    }
 }
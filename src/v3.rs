@@ -106,6 +106,18 @@ impl V3 {
         x.abs() < eps && y.abs() < eps && z.abs() < eps
     }
 
+    pub fn min(self, other: V3) -> V3 {
+        let V3([x1, y1, z1]) = self;
+        let V3([x2, y2, z2]) = other;
+        V3([x1.min(x2), y1.min(y2), z1.min(z2)])
+    }
+
+    pub fn max(self, other: V3) -> V3 {
+        let V3([x1, y1, z1]) = self;
+        let V3([x2, y2, z2]) = other;
+        V3([x1.max(x2), y1.max(y2), z1.max(z2)])
+    }
+
     pub fn reflect(self, normal: V3) -> V3 {
         self - normal * (self.dot(normal) * 2.0)
     }